@@ -1,12 +1,88 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+pub const DISCOVERY_PORT: u16 = 5556;
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct ServerBeacon {
+    pub name: String,
+    pub port: u16,
+    pub version: String,
+}
+
+/// Returned by `/health`, the server answering at all means the process is up.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct Health {
+    pub version: String,
+    pub uptime_secs: u64,
+}
+
+/// Returned by `/ready`, with a 503 unless every check passed.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct Readiness {
+    pub ready: bool,
+    pub version: String,
+    pub uptime_secs: u64,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct ReadinessCheck {
+    pub name: String,
+    pub ok: bool,
+    pub detail: Option<String>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct SyncState {
+    pub seq: u64,
+    pub pricebook_version: u64,
     pub pricebook: Vec<Item>,
     pub accounts: Vec<Account>,
+    pub removed_items: Vec<u32>,
+    pub removed_accounts: Vec<u32>,
+    /// Every bunk whenever accounts are synced, incremental or not, there are only ever a few dozen
+    #[serde(default)]
+    pub bunks: Vec<Bunk>,
+}
+
+/// Asks `/sync` for just the pricebook or just the accounts, the other half comes back empty.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncKind {
+    Pricebook,
+    Accounts,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub enum SyncEvent {
+    AccountChanged {
+        account: Account,
+    },
+    AccountRemoved {
+        account_id: u32,
+    },
+    ItemChanged {
+        item: Item,
+        pricebook_version: u64,
+    },
+    ItemRemoved {
+        item_id: u32,
+        pricebook_version: u64,
+    },
+    /// Several items at once, e.g. the stock counts a sale changed
+    ItemsChanged {
+        items: Vec<Item>,
+        pricebook_version: u64,
+    },
+    BunkChanged {
+        bunk: Bunk,
+    },
+    BunkRemoved {
+        bunk_id: u32,
+    },
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -20,14 +96,86 @@ pub struct TransactionRequest {
     pub tx_id: String,
     pub items: Vec<TxEntry>,
     pub method: TransactionMethod,
+    /// The version the register priced the sale with, registers from before it was sent skip the drift check
+    #[serde(default)]
+    pub pricebook_version: Option<u64>,
+    /// What the register was last told is still owed on a partly paid sale, `None` until then. A payment
+    /// against any other amount is a retry of one the server already took, and gets the last answer back.
+    #[serde(default)]
+    pub remaining: Option<u32>,
+    /// What the register charged for a sale it rang up while offline and is only now sending. The server takes
+    /// this instead of repricing the sale, but only if it's what the items cost at `pricebook_version`.
+    #[serde(default)]
+    pub offline_total: Option<u32>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
 pub enum TransactionStatus {
-    Success { cash_back: u32 },
-    Partial { remaining: u32 },
-    InvalidAccount { account_id: u32 },
-    Failure { reason: String },
+    Success {
+        cash_back: u32,
+    },
+    Partial {
+        remaining: u32,
+    },
+    InvalidAccount {
+        account_id: u32,
+    },
+    PricesChanged {
+        total: u32,
+        pricebook_version: u64,
+    },
+    AccountClosed {
+        account_id: u32,
+    },
+    /// Items in the sale the account isn't allowed to buy
+    ItemsBlocked {
+        items: Vec<u32>,
+    },
+    /// Counted items without enough on hand, only sent when the server blocks sales past zero
+    OutOfStock {
+        items: Vec<u32>,
+    },
+    /// `remaining` is what's left under the limit that was hit, `remaining_today` what's left under the daily limit if there is one
+    LimitExceeded {
+        limit: SpendingLimit,
+        cap: u32,
+        remaining: u32,
+        remaining_today: Option<u32>,
+    },
+    Failure {
+        reason: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub enum SpendingLimit {
+    Daily,
+    /// The last 7 days, today included
+    Weekly,
+    Transaction,
+}
+
+impl Display for SpendingLimit {
+    fn fmt(&self, w: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Daily => write!(w, "daily"),
+            Self::Weekly => write!(w, "weekly"),
+            Self::Transaction => write!(w, "per-sale"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub enum TransactionRejection {
+    EmptyTransaction,
+    InvalidItems {
+        unknown_items: Vec<u32>,
+        zero_quantity: Vec<u32>,
+    },
+    /// More of these than a sale can count, or a line total too big to charge
+    QuantityTooLarge {
+        items: Vec<u32>,
+    },
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
@@ -36,6 +184,29 @@ pub struct Item {
     pub name: String,
     pub gtin: Option<u32>,
     pub price: u32,
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Quantity on hand, `None` for items that aren't counted. Goes negative when more is sold than was counted.
+    #[serde(default)]
+    pub stock: Option<i32>,
+    /// Shown as running low at or below this many
+    #[serde(default)]
+    pub low_stock: Option<u32>,
+    /// What one costs us, from the last delivery received or given when the item was added
+    #[serde(default)]
+    pub unit_cost: Option<u32>,
+}
+
+impl Item {
+    pub fn out_of_stock(&self) -> bool {
+        self.stock.is_some_and(|stock| stock <= 0)
+    }
+
+    pub fn low_on_stock(&self) -> bool {
+        self.stock
+            .zip(self.low_stock)
+            .is_some_and(|(stock, low)| stock > 0 && stock <= low as i32)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -52,6 +223,91 @@ pub struct Account {
     pub overdraft: bool,
     pub discount: u32,
     pub bunk: u32,
+    /// Set when the account is closed out at the end of camp, nothing more can be charged to it
+    #[serde(default)]
+    pub locked: bool,
+    /// Spending caps in cents, `None` for no cap
+    #[serde(default)]
+    pub daily_limit: Option<u32>,
+    #[serde(default)]
+    pub weekly_limit: Option<u32>,
+    #[serde(default)]
+    pub transaction_limit: Option<u32>,
+    /// Items this camper may not buy, for allergies or because a parent asked
+    #[serde(default)]
+    pub blocked_items: Vec<u32>,
+    /// Whole categories this camper may not buy, compared ignoring case
+    #[serde(default)]
+    pub blocked_categories: Vec<String>,
+}
+
+impl Account {
+    pub fn blocks(&self, item: &Item) -> bool {
+        self.blocked_items.contains(&item.id)
+            || item.category.as_ref().is_some_and(|category| {
+                self.blocked_categories
+                    .iter()
+                    .any(|blocked| blocked.eq_ignore_ascii_case(category))
+            })
+    }
+
+    /// What a sale listed at `total` actually charges this account, the register works it out the same way offline
+    pub fn discounted(&self, total: u32) -> u32 {
+        //      e.g. 9000  - (9000  * (       10        / 100)) = 9000 - 900 = 8100
+        total - (total * (self.discount / 100))
+    }
+}
+
+/// A cabin, `Account::bunk` holds its id. Accounts with bunk 0 aren't in one.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct Bunk {
+    pub id: u32,
+    pub name: String,
+    pub counselor: String,
+}
+
+/// What a bunk's campers spent and were given over a period, from `/reports/bunks`.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct BunkSpending {
+    pub bunk: Bunk,
+    pub campers: u32,
+    /// Current total of the campers' balances, whatever the period
+    pub balance: i64,
+    pub deposited: i64,
+    pub spent: i64,
+    pub purchases: u32,
+}
+
+/// What an item or a category brought in over a period against what it cost, from `/reports/margins`.
+/// `uncosted` counts units sold without a known cost, which are left out of `cost` and `margin`.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct Margin {
+    /// Only set on per-item rows
+    pub item_id: Option<u32>,
+    pub name: String,
+    pub category: Option<String>,
+    pub quantity: u32,
+    pub uncosted: u32,
+    /// After account discounts
+    pub revenue: i64,
+    pub cost: i64,
+    /// Revenue less cost, for the units with a known cost
+    pub margin: i64,
+}
+
+/// Per-item rows and per-category totals, best sellers first.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct MarginReport {
+    pub items: Vec<Margin>,
+    pub categories: Vec<Margin>,
+}
+
+/// The same deposit or withdrawal for every open account in a bunk.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct BunkBalanceUpdate {
+    pub bunk: u32,
+    pub amount: u32,
+    pub operation: UpdateOperation,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -76,6 +332,40 @@ impl Display for UpdateOperation {
     }
 }
 
+impl Display for TransactionRejection {
+    fn fmt(&self, w: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::EmptyTransaction => write!(w, "Transaction has no items."),
+            Self::InvalidItems {
+                unknown_items,
+                zero_quantity,
+            } => {
+                let mut problems = Vec::new();
+                if !unknown_items.is_empty() {
+                    problems.push(format!("Unknown items: {}.", join_items(unknown_items)));
+                }
+                if !zero_quantity.is_empty() {
+                    problems.push(format!(
+                        "Items with zero quantity: {}.",
+                        join_items(zero_quantity)
+                    ));
+                }
+                write!(w, "{}", problems.join(" "))
+            }
+            Self::QuantityTooLarge { items } => {
+                write!(w, "Quantity too large for items: {}.", join_items(items))
+            }
+        }
+    }
+}
+
+fn join_items(ids: &[u32]) -> String {
+    ids.iter()
+        .map(|id| format!("{:04}", id))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct CompletedTransaction {
     pub id: String,
@@ -83,6 +373,172 @@ pub struct CompletedTransaction {
     pub cash_back: u32,
 }
 
+/// A completed transaction as kept in the history, for exports and statements.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct HistoryEntry {
+    pub id: String,
+    /// Local time, missing on transactions recorded before it was kept
+    pub created_at: Option<String>,
+    /// Only set on credit sales
+    pub account_id: Option<u32>,
+    pub items: Vec<TxEntry>,
+    /// Unit price of each item when it was sold, empty on transactions recorded before it was kept
+    #[serde(default)]
+    pub prices: HashMap<u32, u32>,
+    /// Unit cost of each item when it was sold, only for items that had one
+    #[serde(default)]
+    pub costs: HashMap<u32, u32>,
+    pub total: Option<u32>,
+    pub cash_back: u32,
+}
+
+/// One movement of an account's credit, `balance` being what was left after it.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct BalanceChange {
+    pub id: u64,
+    pub account_id: u32,
+    pub amount: i32,
+    pub balance: i32,
+    pub reason: BalanceChangeReason,
+    pub tx_id: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub enum BalanceChangeReason {
+    Purchase,
+    Deposit,
+    Withdrawal,
+    Adjustment,
+    Import,
+    /// Closeout payouts, each brings the balance to zero
+    CashRefund,
+    CheckRefund,
+    Donation,
+}
+
+impl Display for BalanceChangeReason {
+    fn fmt(&self, w: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Purchase => write!(w, "Purchase"),
+            Self::Deposit => write!(w, "Deposit"),
+            Self::Withdrawal => write!(w, "Withdrawal"),
+            Self::Adjustment => write!(w, "Adjustment"),
+            Self::Import => write!(w, "Import"),
+            Self::CashRefund => write!(w, "Cash Refund"),
+            Self::CheckRefund => write!(w, "Check Refund"),
+            Self::Donation => write!(w, "Donation"),
+        }
+    }
+}
+
+impl FromStr for BalanceChangeReason {
+    type Err = String;
+
+    fn from_str(reason: &str) -> Result<Self, Self::Err> {
+        match reason {
+            "Purchase" => Ok(Self::Purchase),
+            "Deposit" => Ok(Self::Deposit),
+            "Withdrawal" => Ok(Self::Withdrawal),
+            "Adjustment" => Ok(Self::Adjustment),
+            "Import" => Ok(Self::Import),
+            "Cash Refund" => Ok(Self::CashRefund),
+            "Check Refund" => Ok(Self::CheckRefund),
+            "Donation" => Ok(Self::Donation),
+            _ => Err(format!("Unknown balance change reason {}", reason)),
+        }
+    }
+}
+
+/// One change to an item's quantity on hand, `quantity` being negative for what left the shelf.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct StockMovement {
+    pub id: u64,
+    pub item_id: u32,
+    pub quantity: i32,
+    /// What was on hand after it
+    pub stock: i32,
+    pub reason: StockReason,
+    pub tx_id: Option<String>,
+    pub note: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub enum StockReason {
+    Sale,
+    Receive,
+    /// A recount, `quantity` is the difference from what was expected
+    Adjust,
+    Waste,
+}
+
+impl Display for StockReason {
+    fn fmt(&self, w: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Sale => write!(w, "Sale"),
+            Self::Receive => write!(w, "Receive"),
+            Self::Adjust => write!(w, "Adjust"),
+            Self::Waste => write!(w, "Waste"),
+        }
+    }
+}
+
+impl FromStr for StockReason {
+    type Err = String;
+
+    fn from_str(reason: &str) -> Result<Self, Self::Err> {
+        match reason {
+            "Sale" => Ok(Self::Sale),
+            "Receive" => Ok(Self::Receive),
+            "Adjust" => Ok(Self::Adjust),
+            "Waste" => Ok(Self::Waste),
+            _ => Err(format!("Unknown stock movement reason {}", reason)),
+        }
+    }
+}
+
+/// A restock, entered line by line as a draft and then received, which adds its lines to stock on hand.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct Delivery {
+    pub id: u32,
+    pub supplier: String,
+    pub note: Option<String>,
+    pub created_at: String,
+    /// Set once the delivery has been received, after which it can't be changed
+    pub received_at: Option<String>,
+    pub lines: Vec<DeliveryLine>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct DeliveryLine {
+    pub item_id: u32,
+    pub quantity: u32,
+    /// What one unit cost us, in cents
+    pub unit_cost: u32,
+}
+
+/// What an item cost on one received delivery.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema, PartialEq)]
+pub struct ItemCost {
+    pub item_id: u32,
+    pub delivery_id: u32,
+    pub supplier: String,
+    pub quantity: u32,
+    pub unit_cost: u32,
+    pub received_at: String,
+}
+
+/// A delivery, a recount or something thrown out. `quantity` is what came in or went out, more than zero, or for `Adjust` the new count.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct StockUpdate {
+    pub item_id: u32,
+    pub quantity: i32,
+    pub reason: StockReason,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct PartialTransaction {
     pub id: String,
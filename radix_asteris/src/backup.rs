@@ -0,0 +1,163 @@
+use std::{collections::HashSet, path::PathBuf, time::Duration};
+
+use chrono::{Local, NaiveDateTime, TimeDelta};
+use log::{error, info, warn};
+use thiserror::Error;
+
+use crate::{
+    config::CONFIG,
+    database::{self, DBError},
+    events,
+};
+
+const FILE_PREFIX: &str = "radix_asteris_";
+const TIMESTAMP_FORMAT: &str = "%Y%m%d_%H%M%S";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub taken: NaiveDateTime,
+    pub size: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("Unable to access backup directory: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Database(#[from] DBError),
+    #[error("Backup {0} failed its integrity check: {1}")]
+    Corrupt(String, String),
+}
+
+/// Backs up on startup and then every `backup_interval_minutes`, pruning old backups after each one.
+pub async fn run_schedule() {
+    if CONFIG.backup_interval_minutes == 0 {
+        info!("Scheduled backups are disabled");
+        return;
+    }
+
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(CONFIG.backup_interval_minutes * 60));
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = backup_now().await {
+            error!("Scheduled backup failed: {}", e);
+        }
+        if let Err(e) = prune() {
+            error!("Unable to prune old backups: {}", e);
+        }
+    }
+}
+
+/// Takes a backup and checks it before it shows up in the backup directory, a bad copy is thrown away.
+pub async fn backup_now() -> Result<Backup, BackupError> {
+    std::fs::create_dir_all(&CONFIG.backup_dir)?;
+
+    let taken = Local::now().naive_local();
+    let path = CONFIG.backup_dir.join(format!(
+        "{}{}.db",
+        FILE_PREFIX,
+        taken.format(TIMESTAMP_FORMAT)
+    ));
+    let temp_path = path.with_extension("db.tmp");
+    let _ = std::fs::remove_file(&temp_path);
+
+    database::backup_to(&temp_path).await?;
+    let problems = database::integrity_check(&temp_path)?;
+    if !problems.is_empty() {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(BackupError::Corrupt(
+            path.display().to_string(),
+            problems.join(", "),
+        ));
+    }
+    std::fs::rename(&temp_path, &path)?;
+
+    info!("Backed up database to {}", path.display());
+    Ok(Backup {
+        size: std::fs::metadata(&path)?.len(),
+        path,
+        taken,
+    })
+}
+
+/// Every backup in the backup directory, newest first.
+pub fn list() -> Result<Vec<Backup>, BackupError> {
+    let entries = match std::fs::read_dir(&CONFIG.backup_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(taken) = file_name
+            .strip_prefix(FILE_PREFIX)
+            .and_then(|name| name.strip_suffix(".db"))
+            .and_then(|name| NaiveDateTime::parse_from_str(name, TIMESTAMP_FORMAT).ok())
+        else {
+            continue;
+        };
+
+        backups.push(Backup {
+            path: entry.path(),
+            taken,
+            size: entry.metadata()?.len(),
+        });
+    }
+
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.taken));
+    Ok(backups)
+}
+
+pub fn verify(backup: &Backup) -> Result<(), BackupError> {
+    let problems = database::integrity_check(&backup.path)?;
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(BackupError::Corrupt(
+            backup.path.display().to_string(),
+            problems.join(", "),
+        ))
+    }
+}
+
+/// Swaps the live database for `backup`, after backing up the current one so the restore can itself be undone.
+pub async fn restore(backup: &Backup) -> Result<(), BackupError> {
+    verify(backup)?;
+    let current = backup_now().await?;
+    info!(
+        "Restoring database from {}, previous state saved to {}",
+        backup.path.display(),
+        current.path.display()
+    );
+
+    database::restore_from(&backup.path).await?;
+    events::disconnect_all();
+    Ok(())
+}
+
+/// Keeps every backup from the last `backup_keep_hours`, then the newest of each day up to `backup_keep_days`.
+fn prune() -> Result<(), BackupError> {
+    let now = Local::now().naive_local();
+    let keep_all = TimeDelta::hours(CONFIG.backup_keep_hours as i64);
+    let keep_daily = TimeDelta::days(CONFIG.backup_keep_days as i64);
+
+    let mut kept_days = HashSet::new();
+    for backup in list()? {
+        let age = now - backup.taken;
+        let keep = age < keep_all || (age < keep_daily && kept_days.insert(backup.taken.date()));
+
+        if !keep {
+            info!("Removing old backup {}", backup.path.display());
+            if let Err(e) = std::fs::remove_file(&backup.path) {
+                warn!("Unable to remove {}: {}", backup.path.display(), e);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use std::{path::PathBuf, sync::LazyLock};
+
+use serde::Deserialize;
+
+use crate::ARGS;
+
+// logging is set up from the config, so whatever went wrong loading it waits until logging is there to take it
+static LOADED: LazyLock<(Config, Vec<String>)> = LazyLock::new(Config::load);
+
+pub static CONFIG: LazyLock<Config> = LazyLock::new(|| LOADED.0.clone());
+
+/// Problems loading the config that didn't stop the server, for logging once logging is set up.
+pub fn load_warnings() -> &'static [String] {
+    &LOADED.1
+}
+
+/// Read from `radix_asteris.toml` (or `--config`), any key left out falls back to its default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub bind_address: String,
+    pub port: u16,
+    pub db_path: PathBuf,
+    pub log_dir: PathBuf,
+    /// Level or `RUST_LOG`-style filter, `RUST_LOG` itself wins when set
+    pub log_level: String,
+    /// How many days of log files to keep
+    pub log_keep_days: usize,
+    /// Name registers see when discovering servers on the network
+    pub server_name: String,
+    /// Whether to broadcast discovery beacons at all
+    pub announce: bool,
+    /// Where CSV exports from the admin window are written
+    pub export_dir: PathBuf,
+    pub backup_dir: PathBuf,
+    /// How often to back the database up automatically, 0 turns scheduled backups off
+    pub backup_interval_minutes: u64,
+    /// Every backup younger than this is kept
+    pub backup_keep_hours: u64,
+    /// Past `backup_keep_hours`, the newest backup of each day is kept for this long
+    pub backup_keep_days: u64,
+    /// Refuse sales of counted items beyond what's on hand, instead of letting the count go negative
+    pub block_out_of_stock: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: "0.0.0.0".to_string(),
+            port: 5555,
+            db_path: PathBuf::from("radix_asteris.db"),
+            log_dir: PathBuf::from("logs"),
+            log_level: "info".to_string(),
+            log_keep_days: 14,
+            server_name: std::env::var("COMPUTERNAME")
+                .or_else(|_| std::env::var("HOSTNAME"))
+                .unwrap_or_else(|_| "Radix Asteris".to_string()),
+            announce: true,
+            export_dir: PathBuf::from("exports"),
+            backup_dir: PathBuf::from("backups"),
+            backup_interval_minutes: 60,
+            backup_keep_hours: 24,
+            backup_keep_days: 30,
+            block_out_of_stock: false,
+        }
+    }
+}
+
+impl Config {
+    fn load() -> (Self, Vec<String>) {
+        // the command line under `cargo test` is the test harness's, and tests shouldn't touch a real database
+        if cfg!(test) {
+            let config = Config {
+                db_path: std::env::temp_dir()
+                    .join(format!("radix_asteris_test_{}.db", std::process::id())),
+                announce: false,
+                ..Config::default()
+            };
+            return (config, Vec::new());
+        }
+
+        let mut warnings = Vec::new();
+        let mut config = match std::fs::read_to_string(&ARGS.config) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|e| {
+                panic!("Malformed config file {}: {}", ARGS.config.display(), e)
+            }),
+            Err(e) => {
+                warnings.push(format!(
+                    "Unable to read config file {}, using defaults: {}",
+                    ARGS.config.display(),
+                    e
+                ));
+                Config::default()
+            }
+        };
+
+        if let Some(bind_address) = &ARGS.bind_address {
+            config.bind_address = bind_address.clone();
+        }
+        if let Some(port) = ARGS.port {
+            config.port = port;
+        }
+        if let Some(db_path) = &ARGS.db_path {
+            config.db_path = db_path.clone();
+        }
+        if let Some(log_dir) = &ARGS.log_dir {
+            config.log_dir = log_dir.clone();
+        }
+        if let Some(log_level) = &ARGS.log_level {
+            config.log_level = log_level.clone();
+        }
+
+        (config, warnings)
+    }
+}
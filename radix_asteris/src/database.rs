@@ -1,21 +1,58 @@
-use log::info;
+use itertools::Itertools;
+use log::{error, info};
 use model::TxEntry;
-use std::{collections::HashMap, sync::LazyLock};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
 
-use rusqlite::{Row, fallible_iterator::FallibleIterator};
+use chrono::NaiveDate;
+use rusqlite::{
+    ErrorCode, MAIN_DB, OpenFlags, OptionalExtension, Row, backup::Backup,
+    fallible_iterator::FallibleIterator,
+};
+use serde::de::DeserializeOwned;
 use thiserror::Error;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, MutexGuard};
 
-use model::{Account, BalanceUpdate, CompletedTransaction, Item};
+use model::{
+    Account, BalanceChange, BalanceChangeReason, BalanceUpdate, Bunk, BunkBalanceUpdate,
+    CompletedTransaction, Delivery, DeliveryLine, HistoryEntry, Item, ItemCost, SpendingLimit,
+    StockMovement, StockReason, StockUpdate, SyncEvent, UpdateOperation,
+};
 
-pub static DB: LazyLock<Mutex<rusqlite::Connection>> = LazyLock::new(|| {
-    if let Ok(c) = rusqlite::Connection::open("radix_asteris.db") {
-        Mutex::new(c)
-    } else {
-        panic!("Error initializing database.");
-    }
+use crate::{config::CONFIG, events, metrics};
+
+pub static DB: LazyLock<Mutex<rusqlite::Connection>> = LazyLock::new(|| match open() {
+    Ok(c) => Mutex::new(c),
+    Err(e) => panic!("Error initializing database: {}", e),
 });
 
+fn open() -> rusqlite::Result<rusqlite::Connection> {
+    let connection = rusqlite::Connection::open(&CONFIG.db_path)?;
+    // WAL lets the admin window read while a register is writing, and FULL syncs every commit
+    // so a power cut can't take back a charge the register was told succeeded
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.pragma_update(None, "synchronous", "FULL")?;
+    connection.busy_timeout(Duration::from_secs(5))?;
+    Ok(connection)
+}
+
+/// Every database access goes through here so time spent waiting on the connection shows up in metrics.
+async fn lock() -> MutexGuard<'static, rusqlite::Connection> {
+    if let Ok(connection) = DB.try_lock() {
+        metrics::record_db_lock_wait(Duration::ZERO, false);
+        return connection;
+    }
+
+    let started = Instant::now();
+    let connection = DB.lock().await;
+    metrics::record_db_lock_wait(started.elapsed(), true);
+    connection
+}
+
 #[derive(Debug, Error)]
 pub enum DBError {
     #[error("Unable to find transaction with ID {0}")]
@@ -26,12 +63,66 @@ pub enum DBError {
     JsonFormatError(#[from] serde_json::Error),
     #[error("Duplicative entries found in database. Please contact support ASAP.")]
     DuplicativeEntries,
+    #[error("Transaction with ID {0} has already been completed")]
+    DuplicateTransaction(String),
+    #[error("Unable to insert account {0}: {1}")]
+    AccountInsert(u32, rusqlite::Error),
+    #[error("Account {0} has been closed out")]
+    AccountClosed(u32),
+    #[error("Account {0}'s balance changed since it was loaded, reload it and try again")]
+    BalanceChanged(u32),
+    #[error("Account {0} doesn't have enough credit")]
+    InsufficientCredit(u32),
+    #[error("Bunk {0} still has {1} accounts in it")]
+    BunkInUse(u32, u32),
+    #[error("Account {0} owes {1} cents, take a deposit before closing it out")]
+    OutstandingBalance(u32, i32),
+    #[error("Unable to find item {0}")]
+    ItemNotFound(u32),
+    #[error("Account {account_id} is over its {limit} limit of {cap} cents")]
+    LimitExceeded {
+        account_id: u32,
+        limit: SpendingLimit,
+        cap: u32,
+        remaining: u32,
+        remaining_today: Option<u32>,
+    },
+    #[error("Not enough on hand of items {0:?}")]
+    OutOfStock(Vec<u32>),
+    #[error("{0} stock movements are only recorded by the register")]
+    ManualStockMovement(StockReason),
+    #[error("{0} quantities have to be more than zero")]
+    NonPositiveQuantity(StockReason),
+    #[error("Unable to find delivery {0}")]
+    DeliveryNotFound(u32),
+    #[error("Delivery {0} has already been received")]
+    DeliveryReceived(u32),
+    #[error("Delivery {0} has nothing on it")]
+    EmptyDelivery(u32),
 }
 
 pub async fn wipe() {}
 
+/// Folds the WAL back into the main database file so a clean exit leaves a single self-contained file.
+pub async fn checkpoint() -> Result<(), DBError> {
+    info!("DB | checkpoint");
+    let connection = lock().await;
+    connection.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")?;
+    Ok(())
+}
+
+/// Stored in `user_version` once the schema is fully set up, bump it whenever `create_schema` changes.
+pub const SCHEMA_VERSION: u32 = 13;
+
+// local time, in a format that sorts and compares as text
+const NOW: &str = "strftime('%Y-%m-%d %H:%M:%S', 'now', 'localtime')";
+
 pub async fn init() -> Result<(), DBError> {
-    let connection = DB.lock().await;
+    let connection = lock().await;
+    create_schema(&connection)
+}
+
+fn create_schema(connection: &rusqlite::Connection) -> Result<(), DBError> {
     connection.execute(
         "CREATE TABLE IF NOT EXISTS Pricebook (
         id INTEGER PRIMARY KEY,
@@ -68,6 +159,199 @@ pub async fn init() -> Result<(), DBError> {
     )",
         [],
     )?;
+
+    add_column(connection, "Pricebook", "seq", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(connection, "Accounts", "seq", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(
+        connection,
+        "Accounts",
+        "locked",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column(connection, "Accounts", "daily_limit", "INTEGER")?;
+    add_column(connection, "Accounts", "weekly_limit", "INTEGER")?;
+    add_column(connection, "Accounts", "transaction_limit", "INTEGER")?;
+    add_column(
+        connection,
+        "Accounts",
+        "blocked_items",
+        "JSON NOT NULL DEFAULT '[]'",
+    )?;
+    add_column(
+        connection,
+        "Accounts",
+        "blocked_categories",
+        "JSON NOT NULL DEFAULT '[]'",
+    )?;
+    add_column(connection, "Pricebook", "category", "TEXT")?;
+    add_column(connection, "Pricebook", "stock", "INTEGER")?;
+    add_column(connection, "Pricebook", "low_stock", "INTEGER")?;
+    add_column(connection, "Pricebook", "unit_cost", "INTEGER")?;
+    add_column(connection, "TransactionHistory", "created_at", "TEXT")?;
+    add_column(connection, "TransactionHistory", "account_id", "INTEGER")?;
+    add_column(connection, "TransactionHistory", "total", "INTEGER")?;
+    // unit prices at the time of sale, the pricebook may have moved on by the time anyone asks
+    add_column(connection, "TransactionHistory", "prices", "JSON")?;
+    add_column(connection, "TransactionHistory", "costs", "JSON")?;
+    // what a sale paid in parts came to when its first payment was taken
+    add_column(connection, "PartialTransactions", "total", "INTEGER")?;
+
+    // every change to an account's credit, with what caused it, so balances can be explained after the fact
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS BalanceChanges (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        account_id INTEGER NOT NULL,
+        amount INTEGER NOT NULL,
+        balance INTEGER NOT NULL,
+        reason TEXT NOT NULL,
+        tx_id TEXT,
+        created_at TEXT NOT NULL
+    )",
+        [],
+    )?;
+
+    // every change to an item's quantity on hand, like BalanceChanges is for credit
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS StockMovements (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        item_id INTEGER NOT NULL,
+        quantity INTEGER NOT NULL,
+        stock INTEGER NOT NULL,
+        reason TEXT NOT NULL,
+        tx_id TEXT,
+        note TEXT,
+        created_at TEXT NOT NULL
+    )",
+        [],
+    )?;
+
+    // restocks, drafted line by line and then received into StockMovements
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS Deliveries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        supplier TEXT NOT NULL,
+        note TEXT,
+        created_at TEXT NOT NULL,
+        received_at TEXT
+    );
+    CREATE TABLE IF NOT EXISTS DeliveryLines (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        delivery_id INTEGER NOT NULL,
+        item_id INTEGER NOT NULL,
+        quantity INTEGER NOT NULL,
+        unit_cost INTEGER NOT NULL
+    );",
+    )?;
+
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS Bunks (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        counselor TEXT NOT NULL DEFAULT ''
+    )",
+        [],
+    )?;
+    // accounts only had a bunk number before bunks had a table of their own
+    connection.execute(
+        "INSERT OR IGNORE INTO Bunks (id, name) SELECT DISTINCT bunk, 'Bunk ' || bunk FROM Accounts WHERE bunk != 0",
+        [],
+    )?;
+
+    // every change to an item or account stamps it with the next value of one shared counter,
+    // deletions leave a tombstone behind, and registers ask for everything past their last value
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS SyncSequence (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        value INTEGER NOT NULL
+    );
+    INSERT OR IGNORE INTO SyncSequence (id, value) VALUES (0, 0);
+    CREATE TABLE IF NOT EXISTS Tombstones (
+        kind TEXT NOT NULL,
+        id INTEGER NOT NULL,
+        seq INTEGER NOT NULL
+    );
+    CREATE TRIGGER IF NOT EXISTS pricebook_seq_insert AFTER INSERT ON Pricebook BEGIN
+        UPDATE SyncSequence SET value = value + 1;
+        UPDATE Pricebook SET seq = (SELECT value FROM SyncSequence) WHERE id = NEW.id;
+    END;
+    CREATE TRIGGER IF NOT EXISTS pricebook_seq_update AFTER UPDATE ON Pricebook WHEN NEW.seq = OLD.seq BEGIN
+        UPDATE SyncSequence SET value = value + 1;
+        UPDATE Pricebook SET seq = (SELECT value FROM SyncSequence) WHERE id = NEW.id;
+    END;
+    CREATE TRIGGER IF NOT EXISTS pricebook_seq_delete AFTER DELETE ON Pricebook BEGIN
+        UPDATE SyncSequence SET value = value + 1;
+        INSERT INTO Tombstones (kind, id, seq) VALUES ('item', OLD.id, (SELECT value FROM SyncSequence));
+    END;
+    CREATE TRIGGER IF NOT EXISTS accounts_seq_insert AFTER INSERT ON Accounts BEGIN
+        UPDATE SyncSequence SET value = value + 1;
+        UPDATE Accounts SET seq = (SELECT value FROM SyncSequence) WHERE id = NEW.id;
+    END;
+    CREATE TRIGGER IF NOT EXISTS accounts_seq_update AFTER UPDATE ON Accounts WHEN NEW.seq = OLD.seq BEGIN
+        UPDATE SyncSequence SET value = value + 1;
+        UPDATE Accounts SET seq = (SELECT value FROM SyncSequence) WHERE id = NEW.id;
+    END;
+    CREATE TRIGGER IF NOT EXISTS accounts_seq_delete AFTER DELETE ON Accounts BEGIN
+        UPDATE SyncSequence SET value = value + 1;
+        INSERT INTO Tombstones (kind, id, seq) VALUES ('account', OLD.id, (SELECT value FROM SyncSequence));
+    END;",
+    )?;
+
+    // registers echo this back with each sale, it only moves when a price does so renames and stock
+    // counts don't send a register's sale back as PricesChanged. PriceHistory keeps the price each item had
+    // from each version on, so a sale a register rang up offline can be checked against the prices it had
+    connection.execute_batch(
+        "CREATE TABLE IF NOT EXISTS PricebookVersion (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        value INTEGER NOT NULL
+    );
+    INSERT OR IGNORE INTO PricebookVersion (id, value) VALUES (0, 1);
+    CREATE TABLE IF NOT EXISTS PriceHistory (
+        item_id INTEGER NOT NULL,
+        version INTEGER NOT NULL,
+        price INTEGER NOT NULL,
+        PRIMARY KEY (item_id, version)
+    );
+    INSERT INTO PriceHistory (item_id, version, price)
+        SELECT id, (SELECT value FROM PricebookVersion), price FROM Pricebook
+        WHERE id NOT IN (SELECT item_id FROM PriceHistory);
+    DROP TRIGGER IF EXISTS pricebook_version_price;
+    CREATE TRIGGER pricebook_version_price AFTER UPDATE OF price ON Pricebook WHEN NEW.price != OLD.price BEGIN
+        UPDATE PricebookVersion SET value = value + 1;
+        INSERT OR REPLACE INTO PriceHistory (item_id, version, price)
+            VALUES (NEW.id, (SELECT value FROM PricebookVersion), NEW.price);
+    END;
+    CREATE TRIGGER IF NOT EXISTS price_history_insert AFTER INSERT ON Pricebook BEGIN
+        INSERT OR REPLACE INTO PriceHistory (item_id, version, price)
+            VALUES (NEW.id, (SELECT value FROM PricebookVersion), NEW.price);
+    END;",
+    )?;
+
+    connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    Ok(())
+}
+
+/// Columns added after a table's first release go through here instead of its CREATE TABLE,
+/// so old and freshly created databases end up with the same column order.
+fn add_column(
+    connection: &rusqlite::Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), DBError> {
+    let exists = connection
+        .prepare(&format!(
+            "SELECT 1 FROM pragma_table_info('{}') WHERE name = '{}'",
+            table, column
+        ))?
+        .exists([])?;
+
+    if !exists {
+        info!("DB | adding column {} to {}", column, table);
+        connection.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
     Ok(())
 }
 
@@ -77,7 +361,7 @@ pub async fn get_items(items: Vec<u32>) -> Result<Vec<Item>, DBError> {
     info!("DB | get_items");
     let items = generic_query(
         &format!(
-            "SELECT * FROM Pricebook WHERE id IS ({})",
+            "SELECT * FROM Pricebook WHERE id IN ({})",
             items
                 .iter()
                 .map(|i| i.to_string())
@@ -89,11 +373,19 @@ pub async fn get_items(items: Vec<u32>) -> Result<Vec<Item>, DBError> {
             let name: String = row.get(1)?;
             let gtin: Option<u32> = row.get(2)?;
             let price: u32 = row.get(3)?;
+            let category: Option<String> = row.get("category")?;
+            let stock: Option<i32> = row.get("stock")?;
+            let low_stock: Option<u32> = row.get("low_stock")?;
+            let unit_cost: Option<u32> = row.get("unit_cost")?;
             Ok(Item {
                 id,
                 name,
                 gtin,
                 price,
+                category,
+                stock,
+                low_stock,
+                unit_cost,
             })
         },
     )
@@ -137,75 +429,219 @@ pub async fn get_prices(items: Vec<u32>) -> Result<HashMap<u32, u32>, DBError> {
     Ok(prices)
 }
 
+/// What each item cost as of a pricebook version, leaving out items that didn't exist yet.
+pub async fn get_prices_at(items: Vec<u32>, version: u64) -> Result<HashMap<u32, u32>, DBError> {
+    info!("DB | get_prices_at");
+    let connection = lock().await;
+    let mut statement = connection.prepare(
+        "SELECT price FROM PriceHistory WHERE item_id = ?1 AND version <= ?2 ORDER BY version DESC LIMIT 1",
+    )?;
+    let mut prices = HashMap::new();
+    for id in items {
+        if let Some(price) = statement
+            .query_row((id, version), |row| row.get::<usize, u32>(0))
+            .optional()?
+        {
+            prices.insert(id, price);
+        }
+    }
+    Ok(prices)
+}
+
+/// Unit costs of the items that have one, kept with each sale for the margin report.
+pub async fn get_costs(items: Vec<u32>) -> Result<HashMap<u32, u32>, DBError> {
+    info!("DB | get_costs");
+    if items.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let costs = generic_query(
+        &format!(
+            "SELECT id, unit_cost FROM Pricebook WHERE unit_cost IS NOT NULL AND id IN ({})",
+            items
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        ),
+        |row| Ok((row.get::<usize, u32>(0)?, row.get::<usize, u32>(1)?)),
+    )
+    .await?;
+
+    Ok(costs.into_iter().collect())
+}
+
+/// `total` is what the whole sale comes to, only the first payment's is kept.
 pub async fn create_partial_transaction(
     tx_id: String,
     items: HashMap<u32, u32>,
     difference: u32,
+    total: u32,
 ) -> Result<(), DBError> {
     info!("DB | create_partial_transaction");
-    generic_exec(&format!(
-        "INSERT OR REPLACE INTO PartialTransactions (id, items, remaining) VALUES ('{}', '{}', {})",
-        tx_id,
-        serde_json::to_string(&items)?,
-        difference
-    ))
-    .await?;
+    let connection = lock().await;
+    connection.execute(
+        "INSERT INTO PartialTransactions (id, items, remaining, total) VALUES (?1, ?2, ?3, ?4)
+        ON CONFLICT(id) DO UPDATE SET items = excluded.items, remaining = excluded.remaining",
+        (&tx_id, serde_json::to_string(&items)?, difference, total),
+    )?;
     Ok(())
 }
 
 pub async fn check_partial_transaction(tx_id: String) -> Option<u32> {
     info!("DB | check_partial_transaction with id: {}", tx_id);
 
-    generic_query(
-        &format!("SELECT * FROM PartialTransactions WHERE id = '{}'", tx_id),
-        |row| {
-            let remaining: u32 = row.get(2)?;
-            Ok(remaining)
-        },
-    )
-    .await
-    .map(|res| res.first().copied())
-    .ok()
-    .flatten()
+    let connection = lock().await;
+    connection
+        .query_row(
+            "SELECT remaining FROM PartialTransactions WHERE id = ?1",
+            [&tx_id],
+            |row| row.get::<usize, u32>(0),
+        )
+        .optional()
+        .inspect_err(|e| error!("Error looking up partial transaction {}: {}", tx_id, e))
+        .ok()
+        .flatten()
 }
 
 pub async fn drop_partial_transaction(tx_id: String) -> Result<(), DBError> {
     info!("DB | drop_partial_transaction");
-
-    generic_exec(&format!(
-        "DELETE FROM PartialTransactions WHERE id = '{}'",
-        tx_id
-    ))
-    .await
+    let connection = lock().await;
+    connection.execute("DELETE FROM PartialTransactions WHERE id = ?1", [&tx_id])?;
+    Ok(())
 }
 
+/// Records a completed cash sale and takes what was sold off the shelf. The money is already in the drawer,
+/// so this never refuses a sale for being out of stock, that's checked before any cash is taken.
+/// `total` is what was charged, which for a sale paid in parts is only its last payment's share.
 pub async fn log_transaction(
     tx_id: String,
     items: HashMap<u32, u32>,
     cash_back: u32,
+    total: u32,
 ) -> Result<(), DBError> {
     info!("DB | log_transaction");
     let items_vec = items
         .iter()
         .map(|(&k, &v)| TxEntry { id: k, quantity: v })
         .collect::<Vec<_>>();
-    generic_exec(&format!(
-        "INSERT INTO TransactionHistory (id, items, cash_back) VALUES ('{}', '{}', {})",
-        tx_id,
-        serde_json::to_string(&items_vec)?,
-        cash_back
-    ))
-    .await?;
+    let prices = get_prices(items.keys().copied().collect()).await?;
+    let costs = get_costs(items.keys().copied().collect()).await?;
+
+    let counted = {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        // a sale paid in parts was charged what it came to on its first payment, partial sales
+        // started before that was kept are priced again
+        let total = match transaction
+            .query_row(
+                "SELECT total FROM PartialTransactions WHERE id = ?1",
+                [&tx_id],
+                |row| row.get::<usize, Option<u32>>(0),
+            )
+            .optional()?
+        {
+            Some(Some(total)) => total,
+            Some(None) => items
+                .iter()
+                .map(|(id, quantity)| prices.get(id).copied().unwrap_or_default() * quantity)
+                .sum::<u32>(),
+            None => total,
+        };
+        transaction.execute(
+            &format!(
+                "INSERT INTO TransactionHistory (id, items, cash_back, created_at, total, prices, costs) VALUES (?1, ?2, ?3, {}, ?4, ?5, ?6)",
+                NOW
+            ),
+            (
+                &tx_id,
+                serde_json::to_string(&items_vec)?,
+                cash_back,
+                total,
+                serde_json::to_string(&prices)?,
+                serde_json::to_string(&costs)?,
+            ),
+        )?;
+        let counted = take_stock(&transaction, &items, &tx_id, false)?;
+        transaction.commit()?;
+        counted
+    };
+
+    stock_changed(counted).await;
     Ok(())
 }
 
-pub async fn deduct_balance(account_id: u32, items_total: u32) -> Result<(), DBError> {
-    info!("DB | deduct_balance");
-    generic_exec(&format!(
-        "UPDATE Accounts SET credit = credit - {} WHERE id = {}",
-        items_total, account_id
-    ))
-    .await?;
+/// Deducts the balance and records the transaction as one unit, so a retried request can never
+/// charge an account twice under the same transaction ID, and two sales at once can't both fit under a limit.
+pub async fn complete_credit_transaction(
+    tx_id: String,
+    account: &Account,
+    items: HashMap<u32, u32>,
+    items_total: u32,
+) -> Result<(), DBError> {
+    info!("DB | complete_credit_transaction");
+    let account_id = account.id;
+    let items_vec = items
+        .iter()
+        .map(|(&k, &v)| TxEntry { id: k, quantity: v })
+        .collect::<Vec<_>>();
+    let prices = get_prices(items.keys().copied().collect()).await?;
+    let costs = get_costs(items.keys().copied().collect()).await?;
+    let counted = {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        transaction
+            .execute(
+                &format!(
+                    "INSERT INTO TransactionHistory (id, items, cash_back, created_at, account_id, total, prices, costs) VALUES (?1, ?2, 0, {}, ?3, ?4, ?5, ?6)",
+                    NOW
+                ),
+                (
+                    &tx_id,
+                    serde_json::to_string(&items_vec)?,
+                    account_id,
+                    items_total,
+                    serde_json::to_string(&prices)?,
+                    serde_json::to_string(&costs)?,
+                ),
+            )
+            .map_err(|e| match e.sqlite_error_code() {
+                Some(ErrorCode::ConstraintViolation) => {
+                    DBError::DuplicateTransaction(tx_id.clone())
+                }
+                _ => DBError::Internal(e),
+            })?;
+        // the account may have been closed out or spent down since the register looked it up
+        if transaction.execute(
+            "UPDATE Accounts SET credit = credit - ?1 WHERE id = ?2 AND locked = 0 AND (credit >= ?1 OR overdraft = 1)",
+            (items_total, account_id),
+        )? == 0
+        {
+            let locked = transaction.query_row(
+                "SELECT locked FROM Accounts WHERE id = ?1",
+                [account_id],
+                |row| row.get::<usize, bool>(0),
+            )?;
+            return Err(if locked {
+                DBError::AccountClosed(account_id)
+            } else {
+                DBError::InsufficientCredit(account_id)
+            });
+        }
+        check_limits(&transaction, account, items_total)?;
+        record_balance_change(
+            &transaction,
+            account_id,
+            -(items_total as i64),
+            BalanceChangeReason::Purchase,
+            Some(&tx_id),
+        )?;
+        let counted = take_stock(&transaction, &items, &tx_id, CONFIG.block_out_of_stock)?;
+        transaction.commit()?;
+        counted
+    };
+
+    events::account_changed(account_id).await;
+    stock_changed(counted).await;
     Ok(())
 }
 
@@ -235,30 +671,49 @@ pub async fn get_all_transactions() -> Result<Vec<CompletedTransaction>, DBError
     Ok(transactions)
 }
 
-pub async fn get_transaction(id: u32) -> Result<CompletedTransaction, DBError> {
+pub async fn get_transaction(id: String) -> Result<Option<CompletedTransaction>, DBError> {
     info!("DB | get_transaction");
-    let transactions = generic_query(
-        &format!("SELECT * FROM TransactionHistory WHERE id = '{}'", id),
-        |row| {
-            let id: String = row.get(0)?;
-            let items: String = row.get(1)?;
-            let cash_back: u32 = row.get(2)?;
-            Ok((id, items, cash_back))
-        },
-    )
-    .await?;
+    let transaction = {
+        let connection = lock().await;
+        connection
+            .query_row(
+                "SELECT id, items, cash_back FROM TransactionHistory WHERE id = ?1",
+                [&id],
+                |row| {
+                    let id: String = row.get(0)?;
+                    let items: String = row.get(1)?;
+                    let cash_back: u32 = row.get(2)?;
+                    Ok((id, items, cash_back))
+                },
+            )
+            .optional()?
+    };
 
-    let transaction = transactions
-        .first()
-        .cloned()
-        .ok_or(DBError::NoTransactionFound(id))?;
-    let transaction = CompletedTransaction {
-        id: transaction.0,
-        items: serde_json::from_str(&transaction.1)?,
-        cash_back: transaction.2,
+    let Some((id, items, cash_back)) = transaction else {
+        return Ok(None);
     };
 
-    Ok(transaction)
+    Ok(Some(CompletedTransaction {
+        id,
+        items: serde_json::from_str(&items)?,
+        cash_back,
+    }))
+}
+
+/// What an account has spent on purchases today and over the last 7 days, today included.
+fn recent_spending(
+    connection: &rusqlite::Connection,
+    account_id: u32,
+) -> Result<(u32, u32), DBError> {
+    let (today, week) = connection.query_row(
+        "SELECT
+            COALESCE(SUM(CASE WHEN date(created_at) = date('now', 'localtime') THEN -amount END), 0),
+            COALESCE(SUM(CASE WHEN date(created_at) > date('now', 'localtime', '-7 days') THEN -amount END), 0)
+        FROM BalanceChanges WHERE account_id = ?1 AND reason = ?2",
+        (account_id, BalanceChangeReason::Purchase.to_string()),
+        |row| Ok((row.get::<usize, i64>(0)?, row.get::<usize, i64>(1)?)),
+    )?;
+    Ok((today.max(0) as u32, week.max(0) as u32))
 }
 
 // ------------ Account-oriented ------------
@@ -274,6 +729,12 @@ pub async fn get_account(account_id: u32) -> Result<Account, DBError> {
             let overdraft: bool = row.get::<usize, u32>(3)? != 0;
             let discount: u32 = row.get(4)?;
             let bunk: u32 = row.get(5)?;
+            let locked: bool = row.get("locked")?;
+            let daily_limit: Option<u32> = row.get("daily_limit")?;
+            let weekly_limit: Option<u32> = row.get("weekly_limit")?;
+            let transaction_limit: Option<u32> = row.get("transaction_limit")?;
+            let blocked_items: Vec<u32> = json_column(row, "blocked_items")?;
+            let blocked_categories: Vec<String> = json_column(row, "blocked_categories")?;
             Ok(Account {
                 id,
                 name,
@@ -281,6 +742,12 @@ pub async fn get_account(account_id: u32) -> Result<Account, DBError> {
                 overdraft,
                 discount,
                 bunk,
+                locked,
+                daily_limit,
+                weekly_limit,
+                transaction_limit,
+                blocked_items,
+                blocked_categories,
             })
         },
     )
@@ -302,6 +769,12 @@ pub async fn get_all_accounts() -> Result<Vec<Account>, DBError> {
         let overdraft: bool = row.get(3)?;
         let discount: u32 = row.get(4)?;
         let bunk: u32 = row.get(5)?;
+        let locked: bool = row.get("locked")?;
+        let daily_limit: Option<u32> = row.get("daily_limit")?;
+        let weekly_limit: Option<u32> = row.get("weekly_limit")?;
+        let transaction_limit: Option<u32> = row.get("transaction_limit")?;
+        let blocked_items: Vec<u32> = json_column(row, "blocked_items")?;
+        let blocked_categories: Vec<String> = json_column(row, "blocked_categories")?;
         Ok(Account {
             id,
             name,
@@ -309,6 +782,12 @@ pub async fn get_all_accounts() -> Result<Vec<Account>, DBError> {
             overdraft,
             discount,
             bunk,
+            locked,
+            daily_limit,
+            weekly_limit,
+            transaction_limit,
+            blocked_items,
+            blocked_categories,
         })
     })
     .await?;
@@ -317,35 +796,920 @@ pub async fn get_all_accounts() -> Result<Vec<Account>, DBError> {
 }
 
 /// This acts as both a creator and an updater. It will replace if present, and create if not.
+/// Creates an account or replaces an existing one's details. Whether it's closed is left as it is, that only
+/// changes through `close_account` and `reopen_account`, and a closed account's balance can't be changed here.
 pub async fn insert_account(account: Account) -> Result<(), DBError> {
     info!("DB | insert_account");
-    generic_exec(
-        &format!(
-            "INSERT OR REPLACE INTO Accounts (id, name, credit, overdraft, discount, bunk) VALUES ({}, '{}', {}, {}, {}, {})",
+    let account = {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        let account = upsert_account(&transaction, account)?;
+        transaction.commit()?;
+        account
+    };
+
+    events::publish(SyncEvent::AccountChanged { account });
+    Ok(())
+}
+
+/// Saves an account edited from a copy loaded at `loaded_credit`. The balance is left as it is unless it was
+/// edited, and an edited balance is refused if the account has been charged or topped up since it was loaded.
+pub async fn save_account(mut account: Account, loaded_credit: i32) -> Result<(), DBError> {
+    info!("DB | save_account");
+    let account = {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        let stored = transaction
+            .query_row(
+                "SELECT credit FROM Accounts WHERE id = ?1",
+                [account.id],
+                |row| row.get::<usize, i32>(0),
+            )
+            .optional()?;
+        if let Some(stored) = stored {
+            if account.credit == loaded_credit {
+                account.credit = stored;
+            } else if stored != loaded_credit {
+                return Err(DBError::BalanceChanged(account.id));
+            }
+        }
+        let account = upsert_account(&transaction, account)?;
+        transaction.commit()?;
+        account
+    };
+
+    events::publish(SyncEvent::AccountChanged { account });
+    Ok(())
+}
+
+fn upsert_account(
+    transaction: &rusqlite::Transaction,
+    mut account: Account,
+) -> Result<Account, DBError> {
+    let previous = transaction
+        .query_row(
+            "SELECT credit, locked FROM Accounts WHERE id = ?1",
+            [account.id],
+            |row| Ok((row.get::<usize, i64>(0)?, row.get::<usize, bool>(1)?)),
+        )
+        .optional()?;
+    if let Some((credit, true)) = previous
+        && credit != account.credit as i64
+    {
+        return Err(DBError::AccountClosed(account.id));
+    }
+    account.locked = previous.is_some_and(|(_, locked)| locked);
+    let previous_credit = previous.map(|(credit, _)| credit);
+    transaction.execute(
+        "INSERT INTO Accounts (id, name, credit, overdraft, discount, bunk, daily_limit, weekly_limit, transaction_limit, blocked_items, blocked_categories) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+        ON CONFLICT (id) DO UPDATE SET name = excluded.name, credit = excluded.credit, overdraft = excluded.overdraft, discount = excluded.discount, bunk = excluded.bunk, daily_limit = excluded.daily_limit, weekly_limit = excluded.weekly_limit, transaction_limit = excluded.transaction_limit, blocked_items = excluded.blocked_items, blocked_categories = excluded.blocked_categories",
+        (
             account.id,
-            account.name,
+            &account.name,
             account.credit,
-            account.overdraft as u32,
+            account.overdraft,
             account.discount,
-            account.bunk
-        )
-    ).await
+            account.bunk,
+            account.daily_limit,
+            account.weekly_limit,
+            account.transaction_limit,
+            serde_json::to_string(&account.blocked_items)?,
+            serde_json::to_string(&account.blocked_categories)?,
+        ),
+    )?;
+
+    add_missing_bunk(transaction, account.bunk)?;
+
+    let (amount, reason) = match previous_credit {
+        Some(previous) => (
+            account.credit as i64 - previous,
+            BalanceChangeReason::Adjustment,
+        ),
+        None => (account.credit as i64, BalanceChangeReason::Deposit),
+    };
+    if amount != 0 {
+        record_balance_change(transaction, account.id, amount, reason, None)?;
+    }
+    Ok(account)
+}
+
+/// Inserts every account or none of them. Unlike `insert_account`, an id that's already taken is an error, not an overwrite.
+pub async fn insert_accounts(accounts: &[Account]) -> Result<(), DBError> {
+    info!("DB | insert_accounts");
+    {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO Accounts (id, name, credit, overdraft, discount, bunk, daily_limit, weekly_limit, transaction_limit, blocked_items, blocked_categories) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for account in accounts {
+                statement
+                    .execute((
+                        account.id,
+                        &account.name,
+                        account.credit,
+                        account.overdraft,
+                        account.discount,
+                        account.bunk,
+                        account.daily_limit,
+                        account.weekly_limit,
+                        account.transaction_limit,
+                        serde_json::to_string(&account.blocked_items)?,
+                        serde_json::to_string(&account.blocked_categories)?,
+                    ))
+                    .map_err(|e| DBError::AccountInsert(account.id, e))?;
+                add_missing_bunk(&transaction, account.bunk)?;
+                if account.credit != 0 {
+                    record_balance_change(
+                        &transaction,
+                        account.id,
+                        account.credit as i64,
+                        BalanceChangeReason::Import,
+                        None,
+                    )?;
+                }
+            }
+        }
+        transaction.commit()?;
+    }
+
+    for account in accounts {
+        events::publish(SyncEvent::AccountChanged {
+            account: account.clone(),
+        });
+    }
+    Ok(())
 }
 
 pub async fn remove_account(id: u32) -> Result<(), DBError> {
     info!("DB | remove_account");
-    generic_exec(&format!("DELETE FROM Accounts WHERE id = {}", id)).await
+    generic_exec(&format!("DELETE FROM Accounts WHERE id = {}", id)).await?;
+
+    events::publish(SyncEvent::AccountRemoved { account_id: id });
+    Ok(())
 }
 
 pub async fn update_balance(body: BalanceUpdate) -> Result<(), DBError> {
     info!("DB | update_balance");
-    generic_exec(&format!(
-        "UPDATE Accounts SET credit = credit {} {} WHERE id = {}",
-        body.operation, body.amount, body.id,
-    ))
+    {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        // closed accounts have been paid out, they have to be reopened before their balance moves again
+        let changed = transaction.execute(
+            &format!(
+                "UPDATE Accounts SET credit = credit {} {} WHERE id = {} AND locked = 0",
+                body.operation, body.amount, body.id,
+            ),
+            [],
+        )?;
+        if changed == 0
+            && transaction
+                .query_row(
+                    "SELECT locked FROM Accounts WHERE id = ?1",
+                    [body.id],
+                    |row| row.get::<usize, bool>(0),
+                )
+                .optional()?
+                .unwrap_or_default()
+        {
+            return Err(DBError::AccountClosed(body.id));
+        }
+
+        let (amount, reason) = match body.operation {
+            UpdateOperation::Add => (body.amount as i64, BalanceChangeReason::Deposit),
+            UpdateOperation::Sub => (-(body.amount as i64), BalanceChangeReason::Withdrawal),
+        };
+        if changed > 0 {
+            record_balance_change(&transaction, body.id, amount, reason, None)?;
+        }
+        transaction.commit()?;
+    }
+
+    events::account_changed(body.id).await;
+    Ok(())
+}
+
+/// Pays out what's left on an account at the end of camp and locks it, `payout` being a refund or donation.
+/// Returns the amount paid out.
+pub async fn close_account(account_id: u32, payout: BalanceChangeReason) -> Result<i32, DBError> {
+    info!("DB | close_account");
+    let credit = {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        let (credit, locked) = transaction
+            .query_row(
+                "SELECT credit, locked FROM Accounts WHERE id = ?1",
+                [account_id],
+                |row| Ok((row.get::<usize, i32>(0)?, row.get::<usize, bool>(1)?)),
+            )
+            .optional()?
+            .ok_or(DBError::NoTransactionFound(account_id))?;
+        if locked {
+            return Err(DBError::AccountClosed(account_id));
+        }
+        if credit < 0 {
+            return Err(DBError::OutstandingBalance(account_id, credit));
+        }
+
+        transaction.execute(
+            "UPDATE Accounts SET credit = 0, locked = 1 WHERE id = ?1",
+            [account_id],
+        )?;
+        if credit != 0 {
+            record_balance_change(&transaction, account_id, -(credit as i64), payout, None)?;
+        }
+        transaction.commit()?;
+        credit
+    };
+
+    events::account_changed(account_id).await;
+    Ok(credit)
+}
+
+/// Locks every open account that has nothing left on it. Returns how many were locked.
+pub async fn lock_settled_accounts() -> Result<usize, DBError> {
+    info!("DB | lock_settled_accounts");
+    let ids = {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        let ids = transaction
+            .prepare("SELECT id FROM Accounts WHERE credit = 0 AND locked = 0")?
+            .query_map([], |row| row.get::<usize, u32>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        transaction.execute(
+            "UPDATE Accounts SET locked = 1 WHERE credit = 0 AND locked = 0",
+            [],
+        )?;
+        transaction.commit()?;
+        ids
+    };
+
+    for &id in &ids {
+        events::account_changed(id).await;
+    }
+    Ok(ids.len())
+}
+
+/// Opens a closed account back up, for one closed out by mistake.
+pub async fn reopen_account(account_id: u32) -> Result<(), DBError> {
+    info!("DB | reopen_account");
+    {
+        let connection = lock().await;
+        if connection.execute("UPDATE Accounts SET locked = 0 WHERE id = ?1", [account_id])? == 0 {
+            return Err(DBError::NoTransactionFound(account_id));
+        }
+    }
+
+    events::account_changed(account_id).await;
+    Ok(())
+}
+
+// ------------ Bunk-oriented ------------
+
+pub async fn get_bunks() -> Result<Vec<Bunk>, DBError> {
+    info!("DB | get_bunks");
+    generic_query("SELECT id, name, counselor FROM Bunks ORDER BY id", |row| {
+        Ok(Bunk {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            counselor: row.get(2)?,
+        })
+    })
+    .await
+}
+
+pub async fn insert_bunk(bunk: Bunk) -> Result<(), DBError> {
+    info!("DB | insert_bunk");
+    {
+        let connection = lock().await;
+        connection.execute(
+            "INSERT OR REPLACE INTO Bunks (id, name, counselor) VALUES (?1, ?2, ?3)",
+            (bunk.id, &bunk.name, &bunk.counselor),
+        )?;
+    }
+
+    events::publish(SyncEvent::BunkChanged { bunk });
+    Ok(())
+}
+
+/// Only empty bunks can be removed, move their campers somewhere else first.
+pub async fn remove_bunk(id: u32) -> Result<(), DBError> {
+    info!("DB | remove_bunk");
+    {
+        let connection = lock().await;
+        let campers = connection.query_row(
+            "SELECT COUNT(*) FROM Accounts WHERE bunk = ?1",
+            [id],
+            |row| row.get::<usize, u32>(0),
+        )?;
+        if campers > 0 {
+            return Err(DBError::BunkInUse(id, campers));
+        }
+        connection.execute("DELETE FROM Bunks WHERE id = ?1", [id])?;
+    }
+
+    events::publish(SyncEvent::BunkRemoved { bunk_id: id });
+    Ok(())
+}
+
+/// Applies the same deposit or withdrawal to every open account in a bunk, all or nothing.
+/// Returns how many accounts were updated.
+pub async fn update_bunk_balance(body: BunkBalanceUpdate) -> Result<usize, DBError> {
+    info!("DB | update_bunk_balance");
+    let ids = {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        let ids = transaction
+            .prepare("SELECT id FROM Accounts WHERE bunk = ?1 AND locked = 0")?
+            .query_map([body.bunk], |row| row.get::<usize, u32>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (amount, reason) = match body.operation {
+            UpdateOperation::Add => (body.amount as i64, BalanceChangeReason::Deposit),
+            UpdateOperation::Sub => (-(body.amount as i64), BalanceChangeReason::Withdrawal),
+        };
+        for &id in &ids {
+            transaction.execute(
+                "UPDATE Accounts SET credit = credit + ?1 WHERE id = ?2",
+                (amount, id),
+            )?;
+            record_balance_change(&transaction, id, amount, reason, None)?;
+        }
+        transaction.commit()?;
+        ids
+    };
+
+    for &id in &ids {
+        events::account_changed(id).await;
+    }
+    Ok(ids.len())
+}
+
+// accounts can be given a bunk number nobody has set up yet, it gets a placeholder name until someone does
+fn add_missing_bunk(connection: &rusqlite::Connection, bunk: u32) -> rusqlite::Result<()> {
+    if bunk != 0 {
+        connection.execute(
+            "INSERT OR IGNORE INTO Bunks (id, name) VALUES (?1, 'Bunk ' || ?1)",
+            [bunk],
+        )?;
+    }
+    Ok(())
+}
+
+// ------------ Pricebook-oriented ------------
+
+/// This acts as both a creator and an updater, like `insert_account`. `stock` is left alone, use `update_stock`.
+/// `unit_cost` is only taken for a new item, after that it comes from the deliveries received.
+pub async fn insert_item(mut item: Item) -> Result<(), DBError> {
+    info!("DB | insert_item");
+    {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        // "candy" and "Candy " are the same category, keep whichever spelling is already in use
+        item.category = match item.category.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(category) => Some(
+                transaction
+                    .query_row(
+                        "SELECT category FROM Pricebook WHERE category = ?1 COLLATE NOCASE AND id != ?2 LIMIT 1",
+                        (category, item.id),
+                        |row| row.get(0),
+                    )
+                    .optional()?
+                    .unwrap_or(category.to_string()),
+            ),
+        };
+        transaction.execute(
+            "INSERT INTO Pricebook (id, name, gtin, price, category, low_stock, unit_cost) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT (id) DO UPDATE SET name = excluded.name, gtin = excluded.gtin, price = excluded.price, category = excluded.category, low_stock = excluded.low_stock",
+            (
+                item.id,
+                &item.name,
+                item.gtin,
+                item.price,
+                &item.category,
+                item.low_stock,
+                item.unit_cost,
+            ),
+        )?;
+        transaction.commit()?;
+    }
+
+    // the quantity on hand only moves through update_stock and sales, so send out what's actually stored
+    stock_changed(vec![item.id]).await;
+    Ok(())
+}
+
+pub async fn remove_item(id: u32) -> Result<(), DBError> {
+    info!("DB | remove_item");
+    {
+        let connection = lock().await;
+        connection.execute("DELETE FROM Pricebook WHERE id = ?1", [id])?;
+    }
+
+    events::item_removed(id).await;
+    Ok(())
+}
+
+/// Renames a category on every item in it, and in every account that blocks it so the block keeps applying.
+/// A blank name takes the items out of any category and drops the blocks.
+/// Returns how many items were moved.
+pub async fn rename_category(from: &str, to: &str) -> Result<usize, DBError> {
+    info!("DB | rename_category");
+    let to = Some(to.trim()).filter(|to| !to.is_empty());
+    let (items, accounts) = {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        let items = transaction
+            .prepare("SELECT id FROM Pricebook WHERE category = ?1 COLLATE NOCASE")?
+            .query_map([from], |row| row.get::<usize, u32>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        transaction.execute(
+            "UPDATE Pricebook SET category = ?2 WHERE category = ?1 COLLATE NOCASE",
+            (from, to),
+        )?;
+
+        let blocking = transaction
+            .prepare("SELECT id, blocked_categories FROM Accounts")?
+            .query_map([], |row| {
+                Ok((
+                    row.get::<usize, u32>(0)?,
+                    json_column::<Vec<String>>(row, "blocked_categories")?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|(_, blocked)| blocked.iter().any(|c| c.eq_ignore_ascii_case(from)))
+            .collect::<Vec<_>>();
+        for (id, blocked) in &blocking {
+            let mut renamed = blocked
+                .iter()
+                .filter(|c| !c.eq_ignore_ascii_case(from))
+                .cloned()
+                .collect::<Vec<_>>();
+            if let Some(to) = to.filter(|to| !renamed.iter().any(|c| c.eq_ignore_ascii_case(to))) {
+                renamed.push(to.to_string());
+            }
+            transaction.execute(
+                "UPDATE Accounts SET blocked_categories = ?1 WHERE id = ?2",
+                (serde_json::to_string(&renamed)?, id),
+            )?;
+        }
+        transaction.commit()?;
+        (
+            items,
+            blocking.into_iter().map(|(id, _)| id).collect::<Vec<_>>(),
+        )
+    };
+
+    for item in get_all_items()
+        .await?
+        .into_iter()
+        .filter(|item| items.contains(&item.id))
+    {
+        events::item_changed(item).await;
+    }
+    for id in accounts {
+        events::account_changed(id).await;
+    }
+    Ok(items.len())
+}
+
+/// Every category in use, sorted ignoring case.
+pub async fn get_categories() -> Result<Vec<String>, DBError> {
+    info!("DB | get_categories");
+    generic_query(
+        "SELECT DISTINCT category FROM Pricebook WHERE category IS NOT NULL ORDER BY category COLLATE NOCASE",
+        |row| row.get::<usize, String>(0),
+    )
+    .await
+}
+
+// ------------ Stock-oriented ------------
+
+/// Records a delivery, a recount or waste and returns the item as it stands after.
+/// The first movement on an item that wasn't counted starts counting it from zero.
+pub async fn update_stock(update: StockUpdate) -> Result<Item, DBError> {
+    info!("DB | update_stock");
+    {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        let stock = transaction
+            .query_row(
+                "SELECT stock FROM Pricebook WHERE id = ?1",
+                [update.item_id],
+                |row| row.get::<usize, Option<i32>>(0),
+            )
+            .optional()?
+            .ok_or(DBError::ItemNotFound(update.item_id))?
+            .unwrap_or_default();
+        // a negative delivery or waste would move stock the wrong way
+        if matches!(update.reason, StockReason::Receive | StockReason::Waste)
+            && update.quantity <= 0
+        {
+            return Err(DBError::NonPositiveQuantity(update.reason));
+        }
+        let quantity = match update.reason {
+            StockReason::Receive => update.quantity,
+            StockReason::Waste => -update.quantity,
+            StockReason::Adjust => update.quantity - stock,
+            StockReason::Sale => return Err(DBError::ManualStockMovement(update.reason)),
+        };
+        transaction.execute(
+            "UPDATE Pricebook SET stock = ?1 WHERE id = ?2",
+            (stock + quantity, update.item_id),
+        )?;
+        record_stock_movement(
+            &transaction,
+            update.item_id,
+            quantity,
+            update.reason,
+            None,
+            update.note.as_deref(),
+        )?;
+        transaction.commit()?;
+    }
+
+    stock_changed(vec![update.item_id]).await;
+    get_items(vec![update.item_id])
+        .await?
+        .pop()
+        .ok_or(DBError::ItemNotFound(update.item_id))
+}
+
+/// Stops counting an item, its movements are kept.
+pub async fn clear_stock(item_id: u32) -> Result<(), DBError> {
+    info!("DB | clear_stock");
+    {
+        let connection = lock().await;
+        connection.execute("UPDATE Pricebook SET stock = NULL WHERE id = ?1", [item_id])?;
+    }
+
+    stock_changed(vec![item_id]).await;
+    Ok(())
+}
+
+pub async fn get_stock_movements(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    item_id: Option<u32>,
+) -> Result<Vec<StockMovement>, DBError> {
+    info!("DB | get_stock_movements");
+    let mut filter = export_filter(from, to, None);
+    if let Some(item_id) = item_id {
+        filter.push_str(&format!(" AND item_id = {}", item_id));
+    }
+    generic_query(
+        &format!(
+            "SELECT id, item_id, quantity, stock, reason, tx_id, note, created_at FROM StockMovements WHERE {} ORDER BY id",
+            filter
+        ),
+        |row| {
+            let reason: String = row.get(4)?;
+            Ok(StockMovement {
+                id: row.get(0)?,
+                item_id: row.get(1)?,
+                quantity: row.get(2)?,
+                stock: row.get(3)?,
+                reason: reason.parse().map_err(|e: String| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        4,
+                        rusqlite::types::Type::Text,
+                        e.into(),
+                    )
+                })?,
+                tx_id: row.get(5)?,
+                note: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        },
+    )
+    .await
+}
+
+/// Fails with `LimitExceeded` for the first of the account's spending limits a sale of `total` would go over.
+/// Run inside the sale's transaction, before its purchase is recorded, so spending can't move in between.
+fn check_limits(
+    transaction: &rusqlite::Transaction,
+    account: &Account,
+    total: u32,
+) -> Result<(), DBError> {
+    if account.daily_limit.is_none()
+        && account.weekly_limit.is_none()
+        && account.transaction_limit.is_none()
+    {
+        return Ok(());
+    }
+
+    let (spent_today, spent_week) = recent_spending(transaction, account.id)?;
+    let remaining_today = account
+        .daily_limit
+        .map(|cap| cap.saturating_sub(spent_today));
+
+    let limits = [
+        (SpendingLimit::Transaction, account.transaction_limit, 0),
+        (SpendingLimit::Daily, account.daily_limit, spent_today),
+        (SpendingLimit::Weekly, account.weekly_limit, spent_week),
+    ];
+    for (limit, cap, spent) in limits {
+        let Some(cap) = cap else {
+            continue;
+        };
+        if spent.saturating_add(total) > cap {
+            info!(
+                "Account {} over its {} limit: {} spent, {} more would pass {}",
+                account.id, limit, spent, total, cap
+            );
+            return Err(DBError::LimitExceeded {
+                account_id: account.id,
+                limit,
+                cap,
+                remaining: cap.saturating_sub(spent),
+                remaining_today,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Takes a sale's counted items off the shelf, returning their ids. With `block` set, a sale of more
+/// than is on hand fails with `OutOfStock` and, being inside the sale's transaction, takes nothing.
+fn take_stock(
+    transaction: &rusqlite::Transaction,
+    items: &HashMap<u32, u32>,
+    tx_id: &str,
+    block: bool,
+) -> Result<Vec<u32>, DBError> {
+    let mut counted = Vec::new();
+    let mut short = Vec::new();
+    for (&item_id, &quantity) in items {
+        let Some(stock) = transaction
+            .query_row(
+                "SELECT stock FROM Pricebook WHERE id = ?1",
+                [item_id],
+                |row| row.get::<usize, Option<i32>>(0),
+            )
+            .optional()?
+            .flatten()
+        else {
+            continue;
+        };
+        if block && stock < quantity as i32 {
+            short.push(item_id);
+            continue;
+        }
+        transaction.execute(
+            "UPDATE Pricebook SET stock = stock - ?1 WHERE id = ?2",
+            (quantity, item_id),
+        )?;
+        record_stock_movement(
+            transaction,
+            item_id,
+            -(quantity as i32),
+            StockReason::Sale,
+            Some(tx_id),
+            None,
+        )?;
+        counted.push(item_id);
+    }
+
+    if !short.is_empty() {
+        short.sort();
+        return Err(DBError::OutOfStock(short));
+    }
+    Ok(counted)
+}
+
+fn record_stock_movement(
+    connection: &rusqlite::Connection,
+    item_id: u32,
+    quantity: i32,
+    reason: StockReason,
+    tx_id: Option<&str>,
+    note: Option<&str>,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        &format!(
+            "INSERT INTO StockMovements (item_id, quantity, stock, reason, tx_id, note, created_at) VALUES (?1, ?2, (SELECT stock FROM Pricebook WHERE id = ?1), ?3, ?4, ?5, {})",
+            NOW
+        ),
+        (item_id, quantity, reason.to_string(), tx_id, note),
+    )?;
+    Ok(())
+}
+
+/// Sends registers the items as they now stand, so their on-hand markers keep up.
+async fn stock_changed(item_ids: Vec<u32>) {
+    if item_ids.is_empty() {
+        return;
+    }
+    match get_items(item_ids).await {
+        Ok(items) => events::items_changed(items).await,
+        Err(e) => error!("Unable to publish stock changes: {}", e),
+    }
+}
+
+// ------------ Delivery-oriented ------------
+
+/// Starts a draft delivery and returns its id.
+pub async fn create_delivery(supplier: &str, note: Option<&str>) -> Result<u32, DBError> {
+    info!("DB | create_delivery");
+    let connection = lock().await;
+    connection.execute(
+        &format!(
+            "INSERT INTO Deliveries (supplier, note, created_at) VALUES (?1, ?2, {})",
+            NOW
+        ),
+        (supplier, note),
+    )?;
+    Ok(connection.last_insert_rowid() as u32)
+}
+
+/// Replaces a draft's supplier, note and lines. Received deliveries can't be changed.
+pub async fn save_delivery(delivery: &Delivery) -> Result<(), DBError> {
+    info!("DB | save_delivery");
+    let mut connection = lock().await;
+    let transaction = connection.transaction()?;
+    check_draft(&transaction, delivery.id)?;
+    transaction.execute(
+        "UPDATE Deliveries SET supplier = ?1, note = ?2 WHERE id = ?3",
+        (&delivery.supplier, &delivery.note, delivery.id),
+    )?;
+    transaction.execute(
+        "DELETE FROM DeliveryLines WHERE delivery_id = ?1",
+        [delivery.id],
+    )?;
+    for line in &delivery.lines {
+        transaction.execute(
+            "INSERT INTO DeliveryLines (delivery_id, item_id, quantity, unit_cost) VALUES (?1, ?2, ?3, ?4)",
+            (delivery.id, line.item_id, line.quantity, line.unit_cost),
+        )?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Only drafts can be removed, a received delivery is part of the stock history.
+pub async fn remove_delivery(id: u32) -> Result<(), DBError> {
+    info!("DB | remove_delivery");
+    let mut connection = lock().await;
+    let transaction = connection.transaction()?;
+    check_draft(&transaction, id)?;
+    transaction.execute("DELETE FROM DeliveryLines WHERE delivery_id = ?1", [id])?;
+    transaction.execute("DELETE FROM Deliveries WHERE id = ?1", [id])?;
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Every delivery with its lines, newest first.
+pub async fn get_deliveries() -> Result<Vec<Delivery>, DBError> {
+    info!("DB | get_deliveries");
+    let connection = lock().await;
+    let mut lines = HashMap::<u32, Vec<DeliveryLine>>::new();
+    for line in connection
+        .prepare("SELECT delivery_id, item_id, quantity, unit_cost FROM DeliveryLines ORDER BY id")?
+        .query_map([], |row| {
+            Ok((
+                row.get::<usize, u32>(0)?,
+                DeliveryLine {
+                    item_id: row.get(1)?,
+                    quantity: row.get(2)?,
+                    unit_cost: row.get(3)?,
+                },
+            ))
+        })?
+    {
+        let (delivery_id, line) = line?;
+        lines.entry(delivery_id).or_default().push(line);
+    }
+
+    let deliveries = connection
+        .prepare(
+            "SELECT id, supplier, note, created_at, received_at FROM Deliveries ORDER BY id DESC",
+        )?
+        .query_map([], |row| {
+            let id: u32 = row.get(0)?;
+            Ok(Delivery {
+                id,
+                supplier: row.get(1)?,
+                note: row.get(2)?,
+                created_at: row.get(3)?,
+                received_at: row.get(4)?,
+                lines: lines.remove(&id).unwrap_or_default(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(deliveries)
+}
+
+/// Adds every line to stock on hand as one unit and marks the delivery received.
+/// Items that weren't being counted start counting from what came in, and every item's unit cost becomes
+/// what was paid on this delivery. Returns how many lines were received.
+pub async fn receive_delivery(id: u32) -> Result<usize, DBError> {
+    info!("DB | receive_delivery");
+    let items = {
+        let mut connection = lock().await;
+        let transaction = connection.transaction()?;
+        let supplier = check_draft(&transaction, id)?;
+        let lines = transaction
+            .prepare(
+                "SELECT item_id, quantity, unit_cost FROM DeliveryLines WHERE delivery_id = ?1 ORDER BY id",
+            )?
+            .query_map([id], |row| {
+                Ok((
+                    row.get::<usize, u32>(0)?,
+                    row.get::<usize, i32>(1)?,
+                    row.get::<usize, u32>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        if lines.is_empty() {
+            return Err(DBError::EmptyDelivery(id));
+        }
+
+        let note = format!("Delivery {} from {}", id, supplier);
+        for &(item_id, quantity, unit_cost) in &lines {
+            if transaction.execute(
+                "UPDATE Pricebook SET stock = COALESCE(stock, 0) + ?1, unit_cost = ?2 WHERE id = ?3",
+                (quantity, unit_cost, item_id),
+            )? == 0
+            {
+                return Err(DBError::ItemNotFound(item_id));
+            }
+            record_stock_movement(
+                &transaction,
+                item_id,
+                quantity,
+                StockReason::Receive,
+                None,
+                Some(&note),
+            )?;
+        }
+        transaction.execute(
+            &format!("UPDATE Deliveries SET received_at = {} WHERE id = ?1", NOW),
+            [id],
+        )?;
+        transaction.commit()?;
+        lines
+            .into_iter()
+            .map(|(item_id, _, _)| item_id)
+            .collect::<Vec<_>>()
+    };
+
+    let received = items.len();
+    stock_changed(items.into_iter().sorted().dedup().collect()).await;
+    Ok(received)
+}
+
+/// What items cost on received deliveries, oldest first, optionally for one item.
+pub async fn get_item_costs(item_id: Option<u32>) -> Result<Vec<ItemCost>, DBError> {
+    info!("DB | get_item_costs");
+    generic_query(
+        &format!(
+            "SELECT DeliveryLines.item_id, Deliveries.id, Deliveries.supplier, DeliveryLines.quantity, DeliveryLines.unit_cost, Deliveries.received_at
+            FROM DeliveryLines JOIN Deliveries ON Deliveries.id = DeliveryLines.delivery_id
+            WHERE Deliveries.received_at IS NOT NULL {}
+            ORDER BY Deliveries.received_at, DeliveryLines.id",
+            item_id
+                .map(|item_id| format!("AND DeliveryLines.item_id = {}", item_id))
+                .unwrap_or_default()
+        ),
+        |row| {
+            Ok(ItemCost {
+                item_id: row.get(0)?,
+                delivery_id: row.get(1)?,
+                supplier: row.get(2)?,
+                quantity: row.get(3)?,
+                unit_cost: row.get(4)?,
+                received_at: row.get(5)?,
+            })
+        },
+    )
     .await
 }
 
+// fails unless the delivery exists and hasn't been received, returns its supplier
+fn check_draft(connection: &rusqlite::Connection, id: u32) -> Result<String, DBError> {
+    let (supplier, received_at) = connection
+        .query_row(
+            "SELECT supplier, received_at FROM Deliveries WHERE id = ?1",
+            [id],
+            |row| {
+                Ok((
+                    row.get::<usize, String>(0)?,
+                    row.get::<usize, Option<String>>(1)?,
+                ))
+            },
+        )
+        .optional()?
+        .ok_or(DBError::DeliveryNotFound(id))?;
+    match received_at {
+        Some(_) => Err(DBError::DeliveryReceived(id)),
+        None => Ok(supplier),
+    }
+}
+
 // ------------ Init-oriented ------------
 
 pub async fn get_all_items() -> Result<Vec<Item>, DBError> {
@@ -355,11 +1719,19 @@ pub async fn get_all_items() -> Result<Vec<Item>, DBError> {
         let name: String = row.get(1)?;
         let gtin: Option<u32> = row.get(2)?;
         let price: u32 = row.get(3)?;
+        let category: Option<String> = row.get("category")?;
+        let stock: Option<i32> = row.get("stock")?;
+        let low_stock: Option<u32> = row.get("low_stock")?;
+        let unit_cost: Option<u32> = row.get("unit_cost")?;
         Ok(Item {
             id,
             name,
             gtin,
             price,
+            category,
+            stock,
+            low_stock,
+            unit_cost,
         })
     })
     .await?;
@@ -367,6 +1739,357 @@ pub async fn get_all_items() -> Result<Vec<Item>, DBError> {
     Ok(items)
 }
 
+/// Registers echo this back with each transaction so we can tell when they're pricing off a stale pricebook.
+pub async fn get_pricebook_version() -> Result<u64, DBError> {
+    info!("DB | get_pricebook_version");
+    let version = generic_query("SELECT value FROM PricebookVersion WHERE id = 0", |row| {
+        row.get::<usize, u64>(0)
+    })
+    .await?;
+
+    Ok(version.first().copied().unwrap_or_default())
+}
+
+// ------------ Sync-oriented ------------
+
+pub async fn get_sync_seq() -> Result<u64, DBError> {
+    info!("DB | get_sync_seq");
+    let seq = generic_query("SELECT value FROM SyncSequence WHERE id = 0", |row| {
+        row.get::<usize, u64>(0)
+    })
+    .await?;
+
+    Ok(seq.first().copied().unwrap_or_default())
+}
+
+pub async fn get_items_since(seq: u64) -> Result<Vec<Item>, DBError> {
+    info!("DB | get_items_since");
+    generic_query(
+        &format!("SELECT * FROM Pricebook WHERE seq > {}", seq),
+        |row| {
+            let id: u32 = row.get(0)?;
+            let name: String = row.get(1)?;
+            let gtin: Option<u32> = row.get(2)?;
+            let price: u32 = row.get(3)?;
+            let category: Option<String> = row.get("category")?;
+            let stock: Option<i32> = row.get("stock")?;
+            let low_stock: Option<u32> = row.get("low_stock")?;
+            let unit_cost: Option<u32> = row.get("unit_cost")?;
+            Ok(Item {
+                id,
+                name,
+                gtin,
+                price,
+                category,
+                stock,
+                low_stock,
+                unit_cost,
+            })
+        },
+    )
+    .await
+}
+
+pub async fn get_accounts_since(seq: u64) -> Result<Vec<Account>, DBError> {
+    info!("DB | get_accounts_since");
+    generic_query(
+        &format!("SELECT * FROM Accounts WHERE seq > {}", seq),
+        |row| {
+            let id: u32 = row.get(0)?;
+            let name: String = row.get(1)?;
+            let credit: i32 = row.get(2)?;
+            let overdraft: bool = row.get(3)?;
+            let discount: u32 = row.get(4)?;
+            let bunk: u32 = row.get(5)?;
+            let locked: bool = row.get("locked")?;
+            let daily_limit: Option<u32> = row.get("daily_limit")?;
+            let weekly_limit: Option<u32> = row.get("weekly_limit")?;
+            let transaction_limit: Option<u32> = row.get("transaction_limit")?;
+            let blocked_items: Vec<u32> = json_column(row, "blocked_items")?;
+            let blocked_categories: Vec<String> = json_column(row, "blocked_categories")?;
+            Ok(Account {
+                id,
+                name,
+                credit,
+                overdraft,
+                discount,
+                bunk,
+                locked,
+                daily_limit,
+                weekly_limit,
+                transaction_limit,
+                blocked_items,
+                blocked_categories,
+            })
+        },
+    )
+    .await
+}
+
+/// IDs of rows of the given kind ("item" or "account") deleted since `seq`.
+pub async fn get_removed_since(kind: &str, seq: u64) -> Result<Vec<u32>, DBError> {
+    info!("DB | get_removed_since");
+    generic_query(
+        &format!(
+            "SELECT id FROM Tombstones WHERE kind = '{}' AND seq > {}",
+            kind, seq
+        ),
+        |row| row.get::<usize, u32>(0),
+    )
+    .await
+}
+
+fn record_balance_change(
+    connection: &rusqlite::Connection,
+    account_id: u32,
+    amount: i64,
+    reason: BalanceChangeReason,
+    tx_id: Option<&str>,
+) -> rusqlite::Result<()> {
+    connection.execute(
+        &format!(
+            "INSERT INTO BalanceChanges (account_id, amount, balance, reason, tx_id, created_at) VALUES (?1, ?2, (SELECT credit FROM Accounts WHERE id = ?1), ?3, ?4, {})",
+            NOW
+        ),
+        (account_id, amount, reason.to_string(), tx_id),
+    )?;
+    Ok(())
+}
+
+// ------------ Export-oriented ------------
+
+/// `WHERE` clause for rows whose `created_at` falls within the given days (inclusive) and optionally one account.
+fn export_filter(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    account_id: Option<u32>,
+) -> String {
+    let mut conditions = vec!["1 = 1".to_string()];
+    if let Some(from) = from {
+        conditions.push(format!("date(created_at) >= '{}'", from));
+    }
+    if let Some(to) = to {
+        conditions.push(format!("date(created_at) <= '{}'", to));
+    }
+    if let Some(account_id) = account_id {
+        conditions.push(format!("account_id = {}", account_id));
+    }
+    conditions.join(" AND ")
+}
+
+pub async fn get_history(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    account_id: Option<u32>,
+) -> Result<Vec<HistoryEntry>, DBError> {
+    info!("DB | get_history");
+    let rows = generic_query(
+        &format!(
+            "SELECT id, items, cash_back, created_at, account_id, total, prices, costs FROM TransactionHistory WHERE {} ORDER BY created_at",
+            export_filter(from, to, account_id)
+        ),
+        |row| {
+            let id: String = row.get(0)?;
+            let items: String = row.get(1)?;
+            let cash_back: u32 = row.get(2)?;
+            let created_at: Option<String> = row.get(3)?;
+            let account_id: Option<u32> = row.get(4)?;
+            let total: Option<u32> = row.get(5)?;
+            let prices: Option<String> = row.get(6)?;
+            let costs: Option<String> = row.get(7)?;
+            Ok((
+                id, items, cash_back, created_at, account_id, total, prices, costs,
+            ))
+        },
+    )
+    .await?;
+
+    rows.into_iter()
+        .map(
+            |(id, items, cash_back, created_at, account_id, total, prices, costs)| {
+                Ok(HistoryEntry {
+                    id,
+                    created_at,
+                    account_id,
+                    items: serde_json::from_str(&items)?,
+                    prices: prices
+                        .map(|prices| serde_json::from_str(&prices))
+                        .transpose()?
+                        .unwrap_or_default(),
+                    costs: costs
+                        .map(|costs| serde_json::from_str(&costs))
+                        .transpose()?
+                        .unwrap_or_default(),
+                    total,
+                    cash_back,
+                })
+            },
+        )
+        .collect()
+}
+
+pub async fn get_balance_changes(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    account_id: Option<u32>,
+) -> Result<Vec<BalanceChange>, DBError> {
+    info!("DB | get_balance_changes");
+    generic_query(
+        &format!(
+            "SELECT id, account_id, amount, balance, reason, tx_id, created_at FROM BalanceChanges WHERE {} ORDER BY id",
+            export_filter(from, to, account_id)
+        ),
+        |row| {
+            let reason: String = row.get(4)?;
+            Ok(BalanceChange {
+                id: row.get(0)?,
+                account_id: row.get(1)?,
+                amount: row.get(2)?,
+                balance: row.get(3)?,
+                reason: reason.parse().map_err(|e: String| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        4,
+                        rusqlite::types::Type::Text,
+                        e.into(),
+                    )
+                })?,
+                tx_id: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        },
+    )
+    .await
+}
+
+// ------------ Health-oriented ------------
+
+/// Round trip to SQLite, fails if the database can't be queried at all.
+pub async fn ping() -> Result<(), DBError> {
+    info!("DB | ping");
+    let connection = lock().await;
+    connection.query_row("SELECT 1", [], |_| Ok(()))?;
+    Ok(())
+}
+
+pub async fn get_schema_version() -> Result<u32, DBError> {
+    info!("DB | get_schema_version");
+    let connection = lock().await;
+    connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(DBError::Internal)
+}
+
+// ------------ Backup-oriented ------------
+
+/// Copies the live database to `path` with SQLite's online backup API.
+pub async fn backup_to(path: &Path) -> Result<(), DBError> {
+    info!("DB | backup_to");
+    {
+        let connection = lock().await;
+        connection.backup(MAIN_DB, path, None)?;
+    }
+
+    // the copy comes out in WAL mode like the live database, but a backup should be a single file
+    let backup = rusqlite::Connection::open(path)?;
+    backup.pragma_update(None, "journal_mode", "DELETE")?;
+    Ok(())
+}
+
+/// Runs SQLite's integrity check over a database file and returns every problem found, none means it's sound.
+pub fn integrity_check(path: &Path) -> Result<Vec<String>, DBError> {
+    info!("DB | integrity_check");
+    let connection = rusqlite::Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let mut problems = connection
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get::<usize, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    problems.retain(|problem| problem != "ok");
+
+    // a perfectly healthy database that isn't ours is no use to restore either
+    for table in ["Pricebook", "Accounts"] {
+        let exists = connection
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1")?
+            .exists([table])?;
+        if !exists {
+            problems.push(format!("missing table {}", table));
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Replaces the live database with the backup at `path`. Everything restored is stamped with a fresh sequence number
+/// and anything the backup doesn't have gets a tombstone, so registers catch up through a normal delta sync.
+pub async fn restore_from(path: &Path) -> Result<(), DBError> {
+    info!("DB | restore_from");
+    let mut connection = lock().await;
+
+    let seq: u64 =
+        connection.query_row("SELECT value FROM SyncSequence WHERE id = 0", [], |row| {
+            row.get(0)
+        })?;
+    let pricebook_version: u64 = connection.query_row(
+        "SELECT value FROM PricebookVersion WHERE id = 0",
+        [],
+        |row| row.get(0),
+    )?;
+    let items = table_ids(&connection, "Pricebook")?;
+    let accounts = table_ids(&connection, "Accounts")?;
+
+    let source = rusqlite::Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    Backup::new(&source, &mut connection)?.run_to_completion(100, Duration::ZERO, None)?;
+
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    // the backup may predate columns or tables added since
+    create_schema(&connection)?;
+
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "UPDATE SyncSequence SET value = MAX(value, ?1) + 1 WHERE id = 0",
+        [seq],
+    )?;
+    // the backup's prices may be anything, never hand out a version a register might already hold
+    transaction.execute(
+        "UPDATE PricebookVersion SET value = MAX(value, ?1) + 1 WHERE id = 0",
+        [pricebook_version],
+    )?;
+    transaction.execute_batch(
+        "UPDATE Pricebook SET seq = (SELECT value FROM SyncSequence);
+        UPDATE Accounts SET seq = (SELECT value FROM SyncSequence);
+        DELETE FROM Tombstones WHERE kind = 'item' AND id IN (SELECT id FROM Pricebook);
+        DELETE FROM Tombstones WHERE kind = 'account' AND id IN (SELECT id FROM Accounts);",
+    )?;
+
+    let restored_items = table_ids(&transaction, "Pricebook")?;
+    let restored_accounts = table_ids(&transaction, "Accounts")?;
+    let removed = items
+        .iter()
+        .filter(|id| !restored_items.contains(id))
+        .map(|id| ("item", id))
+        .chain(
+            accounts
+                .iter()
+                .filter(|id| !restored_accounts.contains(id))
+                .map(|id| ("account", id)),
+        );
+    for (kind, id) in removed {
+        transaction.execute(
+            "INSERT INTO Tombstones (kind, id, seq) VALUES (?1, ?2, (SELECT value FROM SyncSequence))",
+            (kind, id),
+        )?;
+    }
+
+    transaction.commit().map_err(DBError::Internal)
+}
+
+fn table_ids(connection: &rusqlite::Connection, table: &str) -> rusqlite::Result<Vec<u32>> {
+    connection
+        .prepare(&format!("SELECT id FROM {}", table))?
+        .query_map([], |row| row.get::<usize, u32>(0))?
+        .collect()
+}
+
 // ------------ Utility-oriented ------------
 
 pub async fn generic_query<T>(
@@ -374,16 +2097,24 @@ pub async fn generic_query<T>(
     applicator: impl FnMut(&Row<'_>) -> rusqlite::Result<T>,
 ) -> Result<Vec<T>, DBError> {
     info!("DB | generic_query");
-    let connection = DB.lock().await;
+    let connection = lock().await;
     let mut statement = connection.prepare(query)?;
     let rows = statement.query(())?;
     let res: Result<Vec<T>, rusqlite::Error> = rows.map(applicator).collect();
     res.map_err(DBError::Internal)
 }
 
+/// Reads a JSON column, a malformed value fails the row like any other conversion error.
+fn json_column<T: DeserializeOwned>(row: &Row<'_>, column: &str) -> rusqlite::Result<T> {
+    let json: String = row.get(column)?;
+    serde_json::from_str(&json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
 pub async fn generic_exec(query: &str) -> Result<(), DBError> {
     info!("DB | generic_exec");
-    let mut connection = DB.lock().await;
+    let mut connection = lock().await;
     let transaction = connection.transaction()?;
     {
         let mut statement = transaction.prepare(query)?;
@@ -394,9 +2125,20 @@ pub async fn generic_exec(query: &str) -> Result<(), DBError> {
 
 pub async fn create_item(item: Item) -> Result<(), DBError> {
     info!("DB | create_item");
-    let connection = DB.lock().await;
-    let mut statement = connection
-        .prepare("INSERT INTO Pricebook (ID, Name, GTIN, Price) VALUES (?1, ?2, ?3, ?4)")?;
-    statement.execute((item.id, item.name, item.gtin, item.price))?;
+    {
+        let connection = lock().await;
+        let mut statement = connection.prepare(
+            "INSERT INTO Pricebook (ID, Name, GTIN, Price, Category) VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        statement.execute((
+            &item.id,
+            &item.name,
+            &item.gtin,
+            &item.price,
+            &item.category,
+        ))?;
+    }
+
+    events::item_changed(item).await;
     Ok(())
 }
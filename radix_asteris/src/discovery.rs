@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use log::{error, info};
+use model::{DISCOVERY_PORT, ServerBeacon};
+use tokio::net::UdpSocket;
+
+use crate::config::CONFIG;
+
+const BEACON_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Broadcasts a beacon on the local network so registers can find us without knowing our IP.
+pub async fn announce() {
+    let socket = match UdpSocket::bind(("0.0.0.0", 0)).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            error!(
+                "Unable to open discovery socket, registers won't find us: {}",
+                e
+            );
+            return;
+        }
+    };
+    if let Err(e) = socket.set_broadcast(true) {
+        error!("Unable to enable broadcast on discovery socket: {}", e);
+        return;
+    }
+
+    let beacon = serde_json::to_vec(&ServerBeacon {
+        name: CONFIG.server_name.clone(),
+        port: CONFIG.port,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+    .expect("Failed to serialize discovery beacon");
+
+    info!("Announcing on UDP port {}", DISCOVERY_PORT);
+    loop {
+        if let Err(e) = socket
+            .send_to(&beacon, ("255.255.255.255", DISCOVERY_PORT))
+            .await
+        {
+            error!("Error sending discovery beacon: {}", e);
+        }
+        tokio::time::sleep(BEACON_INTERVAL).await;
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::LazyLock;
+
+use log::error;
+use model::{Item, SyncEvent};
+use tokio::sync::{broadcast, watch};
+
+use crate::database;
+
+pub static EVENTS: LazyLock<broadcast::Sender<SyncEvent>> =
+    LazyLock::new(|| broadcast::channel(256).0);
+
+static GENERATION: LazyLock<watch::Sender<u64>> = LazyLock::new(|| watch::channel(0).0);
+
+pub fn subscribe() -> broadcast::Receiver<SyncEvent> {
+    EVENTS.subscribe()
+}
+
+/// The receiver's `changed()` resolves the next time `disconnect_all` is called.
+pub fn disconnected() -> watch::Receiver<u64> {
+    GENERATION.subscribe()
+}
+
+/// Ends every open event stream, for when the database changed too much to patch registers one event at a time.
+/// They resync as soon as they reconnect.
+pub fn disconnect_all() {
+    GENERATION.send_modify(|generation| *generation += 1);
+}
+
+pub fn publish(event: SyncEvent) {
+    // sending only fails when no register is listening, which is fine
+    let _ = EVENTS.send(event);
+}
+
+pub async fn account_changed(account_id: u32) {
+    match database::get_account(account_id).await {
+        Ok(account) => publish(SyncEvent::AccountChanged { account }),
+        Err(e) => error!("Unable to publish change for account {}: {}", account_id, e),
+    }
+}
+
+pub async fn item_changed(item: Item) {
+    match database::get_pricebook_version().await {
+        Ok(pricebook_version) => publish(SyncEvent::ItemChanged {
+            item,
+            pricebook_version,
+        }),
+        Err(e) => error!("Unable to publish change for item {}: {}", item.id, e),
+    }
+}
+
+pub async fn items_changed(items: Vec<Item>) {
+    match database::get_pricebook_version().await {
+        Ok(pricebook_version) => publish(SyncEvent::ItemsChanged {
+            items,
+            pricebook_version,
+        }),
+        Err(e) => error!("Unable to publish changes for {} items: {}", items.len(), e),
+    }
+}
+
+pub async fn item_removed(item_id: u32) {
+    match database::get_pricebook_version().await {
+        Ok(pricebook_version) => publish(SyncEvent::ItemRemoved {
+            item_id,
+            pricebook_version,
+        }),
+        Err(e) => error!("Unable to publish removal of item {}: {}", item_id, e),
+    }
+}
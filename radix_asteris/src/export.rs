@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use model::{Account, TxEntry};
+use serde::Deserialize;
+use thiserror::Error;
+use utoipa::IntoParams;
+
+use crate::{
+    database::{self, DBError},
+    report,
+};
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("Unable to write CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("{0}")]
+    Database(#[from] DBError),
+}
+
+/// Which days a report or statement covers, inclusive. Leaving both out covers the whole session.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, IntoParams)]
+pub struct DateRange {
+    /// First day to include, e.g. 2025-07-01
+    pub from: Option<NaiveDate>,
+    /// Last day to include
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    /// Suffix for file names, e.g. `_from_2025-07-01_to_2025-07-31`.
+    pub fn file_suffix(&self) -> String {
+        let mut suffix = String::new();
+        if let Some(from) = self.from {
+            suffix.push_str(&format!("_from_{}", from));
+        }
+        if let Some(to) = self.to {
+            suffix.push_str(&format!("_to_{}", to));
+        }
+        suffix
+    }
+}
+
+/// Which rows to export. Dates are inclusive and compared against when a row was recorded.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, IntoParams)]
+pub struct ExportFilter {
+    /// First day to include, e.g. 2025-07-01
+    pub from: Option<NaiveDate>,
+    /// Last day to include
+    pub to: Option<NaiveDate>,
+    /// Only rows for this account
+    pub account_id: Option<u32>,
+}
+
+impl ExportFilter {
+    pub fn range(&self) -> DateRange {
+        DateRange {
+            from: self.from,
+            to: self.to,
+        }
+    }
+
+    /// Suffix for export file names, e.g. `_from_2025-07-01_to_2025-07-31_account_12`.
+    pub fn file_suffix(&self) -> String {
+        let mut suffix = self.range().file_suffix();
+        if let Some(account_id) = self.account_id {
+            suffix.push_str(&format!("_account_{}", account_id));
+        }
+        suffix
+    }
+}
+
+// Excel only reads a CSV as UTF-8 when it starts with a byte order mark
+const BOM: &str = "\u{feff}";
+
+/// Cents as a plain number of dollars, so spreadsheets treat it as a number rather than text.
+pub fn dollars(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+/// Blocked item ids then blocked categories, in the form the import and the admin window read back.
+pub fn blocked_list(account: &Account) -> String {
+    account
+        .blocked_items
+        .iter()
+        .map(|id| id.to_string())
+        .chain(account.blocked_categories.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn finish(writer: csv::Writer<Vec<u8>>) -> Result<String, ExportError> {
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))?;
+    Ok(format!("{}{}", BOM, String::from_utf8_lossy(&bytes)))
+}
+
+async fn account_names() -> Result<HashMap<u32, String>, ExportError> {
+    Ok(database::get_all_accounts()
+        .await?
+        .into_iter()
+        .map(|account| (account.id, account.name))
+        .collect())
+}
+
+/// `2 x Chips; 1 x Soda`, items deleted from the pricebook since show up by id.
+pub fn describe_items(items: &[TxEntry], item_names: &HashMap<u32, String>) -> String {
+    items
+        .iter()
+        .map(|entry| {
+            let name = item_names
+                .get(&entry.id)
+                .cloned()
+                .unwrap_or_else(|| format!("Item #{:04}", entry.id));
+            format!("{} x {}", entry.quantity, name)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Current balances. Accounts have no history of their own, so only `account_id` applies.
+pub async fn accounts_csv(filter: &ExportFilter) -> Result<String, ExportError> {
+    let mut accounts = database::get_all_accounts().await?;
+    accounts.retain(|account| filter.account_id.is_none_or(|id| account.id == id));
+    accounts.sort_by_key(|account| account.id);
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Id",
+        "Name",
+        "Bunk",
+        "Balance",
+        "Overdraft",
+        "Discount",
+        "Closed",
+        "Daily Limit",
+        "Weekly Limit",
+        "Per-Sale Limit",
+        "Blocked",
+    ])?;
+    for account in accounts {
+        let blocked = blocked_list(&account);
+        let Account {
+            id,
+            name,
+            credit,
+            overdraft,
+            discount,
+            bunk,
+            locked,
+            daily_limit,
+            weekly_limit,
+            transaction_limit,
+            ..
+        } = account;
+        writer.write_record([
+            id.to_string(),
+            name,
+            bunk.to_string(),
+            dollars(credit as i64),
+            if overdraft { "Yes" } else { "No" }.to_string(),
+            discount.to_string(),
+            if locked { "Yes" } else { "No" }.to_string(),
+            daily_limit
+                .map(|cents| dollars(cents as i64))
+                .unwrap_or_default(),
+            weekly_limit
+                .map(|cents| dollars(cents as i64))
+                .unwrap_or_default(),
+            transaction_limit
+                .map(|cents| dollars(cents as i64))
+                .unwrap_or_default(),
+            blocked,
+        ])?;
+    }
+    finish(writer)
+}
+
+/// One row per completed transaction, with its items spelled out by name.
+pub async fn history_csv(filter: &ExportFilter) -> Result<String, ExportError> {
+    let history = database::get_history(filter.from, filter.to, filter.account_id).await?;
+    let account_names = account_names().await?;
+    let item_names = database::get_all_items()
+        .await?
+        .into_iter()
+        .map(|item| (item.id, item.name))
+        .collect::<HashMap<_, _>>();
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Date",
+        "Transaction",
+        "Method",
+        "Account Id",
+        "Account Name",
+        "Items",
+        "Total",
+        "Cash Back",
+    ])?;
+    for entry in history {
+        let account_name = entry
+            .account_id
+            .and_then(|id| account_names.get(&id).cloned())
+            .unwrap_or_default();
+        writer.write_record([
+            entry.created_at.unwrap_or_default(),
+            entry.id,
+            if entry.account_id.is_some() {
+                "Credit"
+            } else {
+                "Cash"
+            }
+            .to_string(),
+            entry
+                .account_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            account_name,
+            describe_items(&entry.items, &item_names),
+            entry
+                .total
+                .map(|total| dollars(total as i64))
+                .unwrap_or_default(),
+            dollars(entry.cash_back as i64),
+        ])?;
+    }
+    finish(writer)
+}
+
+/// Campers, balances, deposits and spending per bunk. `account_id` doesn't apply.
+pub async fn bunks_csv(filter: &ExportFilter) -> Result<String, ExportError> {
+    let report = report::bunk_spending(&filter.range()).await?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Bunk",
+        "Name",
+        "Counselor",
+        "Campers",
+        "Balance",
+        "Deposited",
+        "Spent",
+        "Purchases",
+    ])?;
+    for spending in report {
+        writer.write_record([
+            spending.bunk.id.to_string(),
+            spending.bunk.name,
+            spending.bunk.counselor,
+            spending.campers.to_string(),
+            dollars(spending.balance),
+            dollars(spending.deposited),
+            dollars(spending.spent),
+            spending.purchases.to_string(),
+        ])?;
+    }
+    finish(writer)
+}
+
+/// Revenue, cost of goods and margin per item, then a total row per category.
+pub async fn margins_csv(filter: &ExportFilter) -> Result<String, ExportError> {
+    let report = report::item_margins(&filter.range()).await?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Number",
+        "Name",
+        "Category",
+        "Sold",
+        "Without Cost",
+        "Revenue",
+        "Cost",
+        "Margin",
+    ])?;
+    for margin in report.items.into_iter().chain(report.categories) {
+        writer.write_record([
+            margin.item_id.map(|id| id.to_string()).unwrap_or_default(),
+            margin.name,
+            margin.category.unwrap_or_default(),
+            margin.quantity.to_string(),
+            margin.uncosted.to_string(),
+            dollars(margin.revenue),
+            dollars(margin.cost),
+            dollars(margin.margin),
+        ])?;
+    }
+    finish(writer)
+}
+
+/// Every purchase, deposit and adjustment to account credit, with the balance after each.
+pub async fn balance_changes_csv(filter: &ExportFilter) -> Result<String, ExportError> {
+    let changes = database::get_balance_changes(filter.from, filter.to, filter.account_id).await?;
+    let account_names = account_names().await?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Date",
+        "Account Id",
+        "Account Name",
+        "Reason",
+        "Amount",
+        "Balance",
+        "Transaction",
+    ])?;
+    for change in changes {
+        writer.write_record([
+            change.created_at,
+            change.account_id.to_string(),
+            account_names
+                .get(&change.account_id)
+                .cloned()
+                .unwrap_or_default(),
+            change.reason.to_string(),
+            dollars(change.amount as i64),
+            dollars(change.balance as i64),
+            change.tx_id.unwrap_or_default(),
+        ])?;
+    }
+    finish(writer)
+}
@@ -1,18 +1,36 @@
 pub mod accounts;
+pub mod backups;
+pub mod bunks;
+pub mod closeout;
+pub mod export;
+pub mod import;
 pub mod inventory;
 pub mod menu;
+pub mod receiving;
 pub mod sql;
 
 use accounts::Accounts;
+use backups::Backups;
+use bunks::Bunks;
+use closeout::Closeout;
 use dioxus::prelude::*;
+use export::Export;
+use import::Import;
 use inventory::Inventory;
 use menu::Menu;
+use receiving::Receiving;
 use sql::Sql;
 
 pub enum Form {
     Accounts,
     Inventory,
+    Receiving,
     Sql,
+    Backups,
+    Import,
+    Export,
+    Closeout,
+    Bunks,
 }
 
 #[component]
@@ -27,7 +45,13 @@ pub fn App() -> Element {
             match *form_setter.read() {
                 Form::Accounts => rsx! { Accounts { form_setter } },
                 Form::Inventory => rsx! { Inventory { form_setter } },
+                Form::Receiving => rsx! { Receiving { form_setter } },
                 Form::Sql => rsx! { Sql { form_setter } },
+                Form::Backups => rsx! { Backups { form_setter } },
+                Form::Import => rsx! { Import { form_setter } },
+                Form::Export => rsx! { Export { form_setter } },
+                Form::Closeout => rsx! { Closeout { form_setter } },
+                Form::Bunks => rsx! { Bunks { form_setter } },
             }
         }
     }
@@ -4,7 +4,7 @@ use dioxus::prelude::*;
 use itertools::Itertools;
 use model::Account;
 
-use crate::database;
+use crate::{database, export::blocked_list, import::parse_blocked};
 
 use super::Form;
 
@@ -14,6 +14,8 @@ pub fn Accounts(form_setter: Signal<Form>) -> Element {
 
     let mut displayed_accounts: Signal<BTreeMap<u32, (bool, Account)>> =
         use_signal(|| BTreeMap::new());
+    // balances as they were loaded, so a save can tell whether they've moved since
+    let mut loaded_credit: Signal<BTreeMap<u32, i32>> = use_signal(|| BTreeMap::new());
 
     let load_accounts = move || async move {
         if let Ok(new_accounts) = database::get_all_accounts()
@@ -27,6 +29,12 @@ pub fn Accounts(form_setter: Signal<Form>) -> Element {
                     .collect::<BTreeMap<_, _>>()
             })
         {
+            loaded_credit.set(
+                new_accounts
+                    .iter()
+                    .map(|(id, (_, account))| (*id, account.credit))
+                    .collect(),
+            );
             displayed_accounts.set(new_accounts);
         }
     };
@@ -35,9 +43,13 @@ pub fn Accounts(form_setter: Signal<Form>) -> Element {
         let candidates = displayed_accounts()
             .into_iter()
             .filter(|(_, (edited, _))| *edited);
-        for (_, (_, acc)) in candidates {
-            database::insert_account(acc.clone()).await;
+        for (id, (_, acc)) in candidates {
+            let loaded = loaded_credit().get(&id).copied().unwrap_or(acc.credit);
+            if let Err(e) = database::save_account(acc.clone(), loaded).await {
+                status.set(format!("Unable to save {}: {}", acc.name, e));
+            }
         }
+        load_accounts().await;
     };
 
     let remove_account = move |id: u32| async move {
@@ -116,6 +128,85 @@ pub fn Accounts(form_setter: Signal<Form>) -> Element {
                         }
                     }
                 }
+                // spending limits in cents, left blank for none
+                input {
+                    r#type: "text",
+                    class: "w-1/12 text-center",
+                    placeholder: "Daily",
+                    value: account.daily_limit.map(|limit| limit.to_string()).unwrap_or_default(),
+                    oninput: move |i| {
+                        let value = i.value();
+                        if value.chars().all(|c| c.is_numeric()) {
+                            if let Some((edited, account)) = displayed_accounts.write().get_mut(&id) {
+                                *edited = true;
+                                account.daily_limit = value.parse::<u32>().ok();
+                            }
+                        }
+                    }
+                }
+                input {
+                    r#type: "text",
+                    class: "w-1/12 text-center",
+                    placeholder: "Weekly",
+                    value: account.weekly_limit.map(|limit| limit.to_string()).unwrap_or_default(),
+                    oninput: move |i| {
+                        let value = i.value();
+                        if value.chars().all(|c| c.is_numeric()) {
+                            if let Some((edited, account)) = displayed_accounts.write().get_mut(&id) {
+                                *edited = true;
+                                account.weekly_limit = value.parse::<u32>().ok();
+                            }
+                        }
+                    }
+                }
+                input {
+                    r#type: "text",
+                    class: "w-1/12 text-center",
+                    placeholder: "Per sale",
+                    value: account.transaction_limit.map(|limit| limit.to_string()).unwrap_or_default(),
+                    oninput: move |i| {
+                        let value = i.value();
+                        if value.chars().all(|c| c.is_numeric()) {
+                            if let Some((edited, account)) = displayed_accounts.write().get_mut(&id) {
+                                *edited = true;
+                                account.transaction_limit = value.parse::<u32>().ok();
+                            }
+                        }
+                    }
+                }
+                // item ids and categories this camper can't buy, applied when the field loses focus so typing a comma doesn't get tidied away
+                input {
+                    r#type: "text",
+                    class: "w-1/7 text-center",
+                    placeholder: "Blocked",
+                    title: "Item numbers and categories this camper can't buy, separated by commas",
+                    value: blocked_list(&account),
+                    onchange: move |i| {
+                        let (blocked_items, blocked_categories) = parse_blocked(&i.value());
+                        if let Some((edited, account)) = displayed_accounts.write().get_mut(&id) {
+                            *edited = true;
+                            account.blocked_items = blocked_items;
+                            account.blocked_categories = blocked_categories;
+                        }
+                    }
+                }
+                // closed out accounts can be reopened here if one was closed by mistake
+                div {
+                    class: "w-1/12 text-center",
+                    if account.locked {
+                        button {
+                            class: "btn btn-warning btn-sm",
+                            title: "Closed out, reopen it to charge or change its balance again",
+                            onclick: move |_| async move {
+                                if let Err(e) = database::reopen_account(id).await {
+                                    status.set(format!("Unable to reopen account {}: {}", id, e));
+                                }
+                                load_accounts().await;
+                            },
+                            "Reopen"
+                        }
+                    }
+                }
                 div {
                     class: "w-1/7 text-center",
                     button {
@@ -134,11 +225,23 @@ pub fn Accounts(form_setter: Signal<Form>) -> Element {
     rsx! {
         div {
             class: "grow flex flex-col gap-2 p-2 rounded-md bg-base-200",
-            div { class: "flex w-full justify-center", button { class: "btn btn-primary btn-sm", "Create Account" } }
+            div {
+                class: "flex w-full gap-2 justify-center",
+                button { class: "btn btn-primary btn-sm", "Create Account" }
+                button {
+                    class: "btn btn-secondary btn-sm",
+                    onclick: move |_| form_setter.set(Form::Import),
+                    "Import CSV"
+                }
+            }
             div {
                 class: "grow flex flex-col overflow-y-auto w-full gap-1",
                 {account_elements}
             }
+            div {
+                class: "text-center",
+                {status}
+            }
             div {
                 class: "flex gap-2 justify-center",
                 button {
@@ -0,0 +1,110 @@
+use std::path::PathBuf;
+
+use dioxus::prelude::*;
+use itertools::Itertools;
+
+use crate::backup::{self, Backup};
+
+use super::Form;
+
+#[component]
+pub fn Backups(form_setter: Signal<Form>) -> Element {
+    let mut status = use_signal(|| String::new());
+    let mut backups: Signal<Vec<Backup>> = use_signal(|| Vec::new());
+    // restoring throws away everything since the backup, so it takes a second click
+    let mut pending_restore: Signal<Option<PathBuf>> = use_signal(|| None);
+
+    let mut load_backups = move || match backup::list() {
+        Ok(new_backups) => backups.set(new_backups),
+        Err(e) => status.set(e.to_string()),
+    };
+
+    use_hook(load_backups);
+
+    let backup_now = move || async move {
+        status.set("Backing up...".to_string());
+        match backup::backup_now().await {
+            Ok(backup) => status.set(format!("Backed up to {}", backup.path.display())),
+            Err(e) => status.set(format!("Backup failed: {}", e)),
+        }
+        load_backups();
+    };
+
+    let restore = move |backup: Backup| async move {
+        pending_restore.set(None);
+        status.set("Restoring...".to_string());
+        match backup::restore(&backup).await {
+            Ok(()) => status.set(format!(
+                "Restored backup from {}",
+                backup.taken.format("%Y-%m-%d %H:%M:%S")
+            )),
+            Err(e) => status.set(format!("Restore failed: {}", e)),
+        }
+        load_backups();
+    };
+
+    let backup_elements = backups().into_iter().map(|backup| {
+        let confirming = pending_restore().as_ref() == Some(&backup.path);
+        let verify_backup = backup.clone();
+        let restore_backup = backup.clone();
+
+        rsx! {
+            div {
+                class: "flex justify-between items-center gap-2 w-full max-w-full",
+                div { class: "w-1/3 text-center", {backup.taken.format("%Y-%m-%d %H:%M:%S").to_string()} }
+                div { class: "w-1/6 text-center", {format!("{} KB", backup.size / 1024)} }
+                div {
+                    class: "w-1/2 flex gap-2 justify-center",
+                    button {
+                        class: "btn btn-secondary btn-sm",
+                        onclick: move |_| match backup::verify(&verify_backup) {
+                            Ok(()) => status.set(format!("{} passed its integrity check", verify_backup.path.display())),
+                            Err(e) => status.set(e.to_string()),
+                        },
+                        "Verify"
+                    }
+                    if confirming {
+                        button {
+                            class: "btn btn-error btn-sm",
+                            onclick: move |_| restore(restore_backup.clone()),
+                            "Confirm Restore"
+                        }
+                        button {
+                            class: "btn btn-sm",
+                            onclick: move |_| pending_restore.set(None),
+                            "Cancel"
+                        }
+                    } else {
+                        button {
+                            class: "btn btn-warning btn-sm",
+                            onclick: move |_| pending_restore.set(Some(restore_backup.path.clone())),
+                            "Restore"
+                        }
+                    }
+                }
+            }
+        }
+    }).intersperse(rsx! { div { class: "h-[2px] bg-base-100" } });
+
+    rsx! {
+        div {
+            class: "grow flex flex-col gap-2 p-2 rounded-md bg-base-200",
+            div {
+                class: "flex w-full justify-center",
+                button {
+                    class: "btn btn-primary btn-sm",
+                    onclick: move |_| backup_now(),
+                    "Backup Now"
+                }
+            }
+            div {
+                class: "grow flex flex-col overflow-y-auto w-full gap-1",
+                {backup_elements}
+            }
+            div {
+                class: "h-[1em] text-center",
+                {status}
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+
+use dioxus::prelude::*;
+use model::{Bunk, BunkBalanceUpdate, BunkSpending, UpdateOperation};
+
+use crate::{
+    database,
+    export::{DateRange, dollars},
+    import::parse_amount,
+    report,
+};
+
+use super::Form;
+
+#[component]
+pub fn Bunks(form_setter: Signal<Form>) -> Element {
+    let mut status = use_signal(|| String::new());
+    // edit flag per bunk so saving only touches what changed
+    let mut bunks: Signal<BTreeMap<u32, (bool, BunkSpending)>> = use_signal(|| BTreeMap::new());
+    let mut amount = use_signal(|| String::new());
+    let mut new_bunk = use_signal(|| Bunk {
+        id: 0,
+        name: String::new(),
+        counselor: String::new(),
+    });
+
+    let load = move || async move {
+        match report::bunk_spending(&DateRange::default()).await {
+            Ok(report) => bunks.set(
+                report
+                    .into_iter()
+                    // campers without a bunk show up in the report, but there's no bunk 0 to edit
+                    .filter(|spending| spending.bunk.id != 0)
+                    .map(|spending| (spending.bunk.id, (false, spending)))
+                    .collect(),
+            ),
+            Err(e) => status.set(e.to_string()),
+        }
+    };
+
+    use_future(load);
+
+    let save = move || async move {
+        let edited = bunks()
+            .into_values()
+            .filter(|(edited, _)| *edited)
+            .map(|(_, spending)| spending.bunk)
+            .collect::<Vec<_>>();
+        for bunk in &edited {
+            if let Err(e) = database::insert_bunk(bunk.clone()).await {
+                status.set(e.to_string());
+                return;
+            }
+        }
+        status.set(format!("Saved {} bunks", edited.len()));
+        load().await;
+    };
+
+    let add_bunk = move || async move {
+        let bunk = new_bunk();
+        if bunk.id == 0 || bunk.name.trim().is_empty() {
+            status.set("A new bunk needs a number above 0 and a name".to_string());
+            return;
+        }
+        if bunks().contains_key(&bunk.id) {
+            status.set(format!("Bunk {} already exists", bunk.id));
+            return;
+        }
+        match database::insert_bunk(bunk.clone()).await {
+            Ok(()) => {
+                status.set(format!("Added {}", bunk.name));
+                new_bunk.set(Bunk {
+                    id: 0,
+                    name: String::new(),
+                    counselor: String::new(),
+                });
+            }
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let remove_bunk = move |id: u32| async move {
+        match database::remove_bunk(id).await {
+            Ok(()) => status.set(format!("Removed bunk {}", id)),
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let bulk_update = move |bunk: u32, operation: UpdateOperation| async move {
+        let Some(cents) = parse_amount(&amount()).filter(|&cents| cents > 0) else {
+            status.set(format!("Invalid amount \"{}\"", amount()));
+            return;
+        };
+        let verb = match operation {
+            UpdateOperation::Add => "Added",
+            UpdateOperation::Sub => "Removed",
+        };
+        match database::update_bunk_balance(BunkBalanceUpdate {
+            bunk,
+            amount: cents as u32,
+            operation,
+        })
+        .await
+        {
+            Ok(updated) => status.set(format!(
+                "{} ${} for {} campers in bunk {}",
+                verb,
+                dollars(cents as i64),
+                updated,
+                bunk
+            )),
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let bunk_elements = bunks().into_iter().map(|(id, (_, spending))| {
+        rsx! {
+            tr {
+                key: "{id}",
+                td { {id.to_string()} }
+                td {
+                    input {
+                        class: "input input-sm",
+                        value: spending.bunk.name.clone(),
+                        oninput: move |i| {
+                            if let Some((edited, spending)) = bunks.write().get_mut(&id) {
+                                *edited = true;
+                                spending.bunk.name = i.value();
+                            }
+                        }
+                    }
+                }
+                td {
+                    input {
+                        class: "input input-sm",
+                        value: spending.bunk.counselor.clone(),
+                        oninput: move |i| {
+                            if let Some((edited, spending)) = bunks.write().get_mut(&id) {
+                                *edited = true;
+                                spending.bunk.counselor = i.value();
+                            }
+                        }
+                    }
+                }
+                td { {spending.campers.to_string()} }
+                td { {format!("${}", dollars(spending.balance))} }
+                td { {format!("${}", dollars(spending.spent))} }
+                td {
+                    class: "flex gap-1",
+                    button {
+                        class: "btn btn-success btn-sm",
+                        onclick: move |_| bulk_update(id, UpdateOperation::Add),
+                        "Add"
+                    }
+                    button {
+                        class: "btn btn-warning btn-sm",
+                        onclick: move |_| bulk_update(id, UpdateOperation::Sub),
+                        "Remove"
+                    }
+                    button {
+                        class: "btn btn-error btn-sm",
+                        disabled: spending.campers > 0,
+                        onclick: move |_| remove_bunk(id),
+                        "Delete"
+                    }
+                }
+            }
+        }
+    });
+
+    rsx! {
+        div {
+            class: "grow flex flex-col gap-2 p-2 rounded-md bg-base-200",
+            div {
+                class: "flex w-full gap-2 justify-center items-center",
+                input {
+                    class: "input input-sm w-20",
+                    placeholder: "Number",
+                    value: if new_bunk().id == 0 { String::new() } else { new_bunk().id.to_string() },
+                    oninput: move |i| new_bunk.write().id = i.value().trim().parse().unwrap_or_default(),
+                }
+                input {
+                    class: "input input-sm w-48",
+                    placeholder: "Name",
+                    value: new_bunk().name,
+                    oninput: move |i| new_bunk.write().name = i.value(),
+                }
+                input {
+                    class: "input input-sm w-48",
+                    placeholder: "Counselor",
+                    value: new_bunk().counselor,
+                    oninput: move |i| new_bunk.write().counselor = i.value(),
+                }
+                button {
+                    class: "btn btn-primary btn-sm",
+                    onclick: move |_| add_bunk(),
+                    "Add Bunk"
+                }
+            }
+            div {
+                class: "flex w-full gap-2 justify-center items-center",
+                "Amount for Add/Remove"
+                input {
+                    class: "input input-sm w-32",
+                    placeholder: "$",
+                    value: amount,
+                    oninput: move |i| amount.set(i.value()),
+                }
+            }
+            div {
+                class: "grow overflow-y-auto w-full",
+                table {
+                    class: "table table-sm",
+                    thead {
+                        tr {
+                            th { "Bunk" }
+                            th { "Name" }
+                            th { "Counselor" }
+                            th { "Campers" }
+                            th { "Balance" }
+                            th { "Spent" }
+                            th { "Campers' Balances" }
+                        }
+                    }
+                    tbody { {bunk_elements} }
+                }
+            }
+            div {
+                class: "text-center",
+                {status}
+            }
+            div {
+                class: "flex gap-2 justify-center",
+                button {
+                    class: "btn btn-success",
+                    onclick: move |_| save(),
+                    "Save"
+                }
+                button {
+                    class: "btn btn-error",
+                    onclick: move |_| load(),
+                    "Discard"
+                }
+            }
+        }
+    }
+}
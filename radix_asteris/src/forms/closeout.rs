@@ -0,0 +1,169 @@
+use dioxus::prelude::*;
+use model::{Account, BalanceChangeReason};
+
+use crate::{database, export::dollars};
+
+use super::Form;
+
+#[derive(Clone, Default, PartialEq)]
+struct Totals {
+    cash: i64,
+    check: i64,
+    donated: i64,
+}
+
+#[component]
+pub fn Closeout(form_setter: Signal<Form>) -> Element {
+    let mut status = use_signal(|| String::new());
+    let mut accounts: Signal<Vec<Account>> = use_signal(|| Vec::new());
+    let mut totals = use_signal(Totals::default);
+
+    let load = move || async move {
+        match database::get_all_accounts().await {
+            Ok(mut new_accounts) => {
+                new_accounts.sort_by(|a, b| a.bunk.cmp(&b.bunk).then(a.name.cmp(&b.name)));
+                accounts.set(new_accounts);
+            }
+            Err(e) => status.set(e.to_string()),
+        }
+
+        match database::get_balance_changes(None, None, None).await {
+            Ok(changes) => {
+                let mut new_totals = Totals::default();
+                for change in changes {
+                    // payouts are recorded as money leaving the account
+                    let amount = -(change.amount as i64);
+                    match change.reason {
+                        BalanceChangeReason::CashRefund => new_totals.cash += amount,
+                        BalanceChangeReason::CheckRefund => new_totals.check += amount,
+                        BalanceChangeReason::Donation => new_totals.donated += amount,
+                        _ => {}
+                    }
+                }
+                totals.set(new_totals);
+            }
+            Err(e) => status.set(e.to_string()),
+        }
+    };
+
+    use_future(load);
+
+    let close = move |account: Account, payout: BalanceChangeReason| async move {
+        match database::close_account(account.id, payout).await {
+            Ok(amount) => status.set(format!(
+                "Closed {}, ${} recorded as {}",
+                account.name,
+                dollars(amount as i64),
+                payout
+            )),
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let lock_settled = move || async move {
+        match database::lock_settled_accounts().await {
+            Ok(locked) => status.set(format!("Locked {} accounts", locked)),
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let open_accounts = accounts()
+        .into_iter()
+        .filter(|account| !account.locked)
+        .collect::<Vec<_>>();
+    let settled = open_accounts
+        .iter()
+        .filter(|account| account.credit == 0)
+        .count();
+    let locked = accounts().len() - open_accounts.len();
+
+    let account_elements = open_accounts
+        .into_iter()
+        .filter(|account| account.credit != 0)
+        .map(|account| {
+            let (cash, check, donate) = (account.clone(), account.clone(), account.clone());
+            rsx! {
+                tr {
+                    key: "{account.id}",
+                    td { {account.id.to_string()} }
+                    td { {account.name.clone()} }
+                    td { {account.bunk.to_string()} }
+                    td { {format!("${}", dollars(account.credit as i64))} }
+                    td {
+                        class: "flex gap-2",
+                        if account.credit > 0 {
+                            button {
+                                class: "btn btn-success btn-sm",
+                                onclick: move |_| close(cash.clone(), BalanceChangeReason::CashRefund),
+                                "Cash Refund"
+                            }
+                            button {
+                                class: "btn btn-info btn-sm",
+                                onclick: move |_| close(check.clone(), BalanceChangeReason::CheckRefund),
+                                "Check Refund"
+                            }
+                            button {
+                                class: "btn btn-secondary btn-sm",
+                                onclick: move |_| close(donate.clone(), BalanceChangeReason::Donation),
+                                "Donate"
+                            }
+                        } else {
+                            span {
+                                class: "text-error",
+                                "Owes money, take a deposit before closing"
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+    rsx! {
+        div {
+            class: "grow flex flex-col gap-2 p-2 rounded-md bg-base-200",
+            div {
+                class: "flex w-full gap-4 justify-center text-sm",
+                span { {format!("Refunded in cash: ${}", dollars(totals().cash))} }
+                span { {format!("Refunded by check: ${}", dollars(totals().check))} }
+                span { {format!("Donated: ${}", dollars(totals().donated))} }
+                span { {format!("{} accounts locked", locked)} }
+            }
+            div {
+                class: "grow overflow-y-auto w-full",
+                table {
+                    class: "table table-sm",
+                    thead {
+                        tr {
+                            th { "Id" }
+                            th { "Name" }
+                            th { "Bunk" }
+                            th { "Balance" }
+                            th { "Payout" }
+                        }
+                    }
+                    tbody { {account_elements} }
+                }
+            }
+            div {
+                class: "text-center",
+                {status}
+            }
+            div {
+                class: "flex gap-2 justify-center",
+                button {
+                    class: "btn btn-warning",
+                    disabled: settled == 0,
+                    onclick: move |_| lock_settled(),
+                    {format!("Lock {} Settled Accounts", settled)}
+                }
+                button {
+                    class: "btn btn-error",
+                    onclick: move |_| form_setter.set(Form::Accounts),
+                    "Back"
+                }
+            }
+        }
+    }
+}
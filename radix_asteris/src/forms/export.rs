@@ -0,0 +1,203 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use dioxus::prelude::*;
+
+use crate::{
+    config::CONFIG,
+    export::{self, DateRange, ExportError, ExportFilter},
+    statement,
+};
+
+use super::Form;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ExportKind {
+    Accounts,
+    History,
+    BalanceChanges,
+    Bunks,
+    Margins,
+}
+
+async fn write_export(export: ExportKind, filter: ExportFilter) -> Result<PathBuf, String> {
+    let (name, csv) = match export {
+        ExportKind::Accounts => ("accounts", export::accounts_csv(&filter).await),
+        ExportKind::History => ("history", export::history_csv(&filter).await),
+        ExportKind::BalanceChanges => (
+            "balance_changes",
+            export::balance_changes_csv(&filter).await,
+        ),
+        ExportKind::Bunks => ("bunks", export::bunks_csv(&filter).await),
+        ExportKind::Margins => ("margins", export::margins_csv(&filter).await),
+    };
+    let csv = csv.map_err(|e: ExportError| e.to_string())?;
+
+    let path = CONFIG
+        .export_dir
+        .join(format!("{}{}.csv", name, filter.file_suffix()));
+    std::fs::create_dir_all(&CONFIG.export_dir)
+        .and_then(|_| std::fs::write(&path, csv))
+        .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum StatementKind {
+    Account(u32),
+    Bunk(u32),
+}
+
+// statements are opened in the browser, which prints them or saves them as PDF
+async fn write_statement(kind: StatementKind, range: DateRange) -> Result<PathBuf, String> {
+    let (name, html) = match kind {
+        StatementKind::Account(id) => (
+            format!("statement_account_{}", id),
+            statement::account_statement(id, &range).await,
+        ),
+        StatementKind::Bunk(bunk) => (
+            format!("statements_bunk_{}", bunk),
+            statement::bunk_statements(bunk, &range).await,
+        ),
+    };
+    let html = html.map_err(|e| e.to_string())?;
+
+    let path = CONFIG
+        .export_dir
+        .join(format!("{}{}.html", name, range.file_suffix()));
+    std::fs::create_dir_all(&CONFIG.export_dir)
+        .and_then(|_| std::fs::write(&path, html))
+        .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+    open::that(&path).map_err(|e| format!("Unable to open {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+#[component]
+pub fn Export(form_setter: Signal<Form>) -> Element {
+    let mut status = use_signal(|| String::new());
+    let mut from: Signal<Option<NaiveDate>> = use_signal(|| None);
+    let mut to: Signal<Option<NaiveDate>> = use_signal(|| None);
+    let mut statement_target = use_signal(|| String::new());
+
+    let run_export = move |export: ExportKind| async move {
+        let filter = ExportFilter {
+            from: from(),
+            to: to(),
+            account_id: None,
+        };
+        match write_export(export, filter).await {
+            Ok(path) => status.set(format!("Exported to {}", path.display())),
+            Err(e) => status.set(e),
+        }
+    };
+
+    let run_statement = move |bunk: bool| async move {
+        let Ok(target) = statement_target().trim().parse::<u32>() else {
+            status.set(format!(
+                "Enter a {} number",
+                if bunk { "bunk" } else { "account" }
+            ));
+            return;
+        };
+        let kind = if bunk {
+            StatementKind::Bunk(target)
+        } else {
+            StatementKind::Account(target)
+        };
+        let range = DateRange {
+            from: from(),
+            to: to(),
+        };
+        match write_statement(kind, range).await {
+            Ok(path) => status.set(format!("Statement saved to {}", path.display())),
+            Err(e) => status.set(e),
+        }
+    };
+
+    rsx! {
+        div {
+            class: "grow flex flex-col gap-2 p-2 rounded-md bg-base-200",
+            div {
+                class: "flex w-full gap-2 justify-center items-center",
+                "From"
+                input {
+                    r#type: "date",
+                    class: "input input-sm w-auto",
+                    oninput: move |i| from.set(NaiveDate::parse_from_str(&i.value(), "%Y-%m-%d").ok()),
+                }
+                "To"
+                input {
+                    r#type: "date",
+                    class: "input input-sm w-auto",
+                    oninput: move |i| to.set(NaiveDate::parse_from_str(&i.value(), "%Y-%m-%d").ok()),
+                }
+            }
+            div {
+                class: "flex w-full gap-2 justify-center",
+                button {
+                    class: "btn btn-primary",
+                    onclick: move |_| run_export(ExportKind::Accounts),
+                    "Export Accounts"
+                }
+                button {
+                    class: "btn btn-primary",
+                    onclick: move |_| run_export(ExportKind::History),
+                    "Export Purchase History"
+                }
+                button {
+                    class: "btn btn-primary",
+                    onclick: move |_| run_export(ExportKind::BalanceChanges),
+                    "Export Balance Changes"
+                }
+                button {
+                    class: "btn btn-primary",
+                    onclick: move |_| run_export(ExportKind::Bunks),
+                    "Export Bunk Spending"
+                }
+                button {
+                    class: "btn btn-primary",
+                    onclick: move |_| run_export(ExportKind::Margins),
+                    "Export Item Margins"
+                }
+            }
+            div {
+                class: "text-sm text-center italic",
+                "Balances are always current, the dates only filter history, balance changes and margins."
+            }
+            div {
+                class: "flex w-full gap-2 justify-center items-center",
+                "Statements for"
+                input {
+                    class: "input input-sm w-32",
+                    placeholder: "Account or bunk",
+                    value: statement_target,
+                    oninput: move |i| statement_target.set(i.value()),
+                }
+                button {
+                    class: "btn btn-primary",
+                    onclick: move |_| run_statement(false),
+                    "Account Statement"
+                }
+                button {
+                    class: "btn btn-primary",
+                    onclick: move |_| run_statement(true),
+                    "Bunk Statements"
+                }
+            }
+            div {
+                class: "flex gap-2 justify-center items-center",
+                {status}
+                button {
+                    class: "btn btn-sm",
+                    onclick: move |_| {
+                        let _ = std::fs::create_dir_all(&CONFIG.export_dir);
+                        if let Err(e) = open::that(&CONFIG.export_dir) {
+                            status.set(format!("Unable to open {}: {}", CONFIG.export_dir.display(), e));
+                        }
+                    },
+                    "Open Folder"
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use dioxus::prelude::*;
+
+use crate::import::{self, ImportPreview};
+
+use super::Form;
+
+#[component]
+pub fn Import(form_setter: Signal<Form>) -> Element {
+    let mut status = use_signal(|| String::new());
+    let mut preview: Signal<Option<ImportPreview>> = use_signal(|| None);
+
+    let load_file = move |event: FormEvent| async move {
+        preview.set(None);
+        let Some(files) = event.files() else {
+            return;
+        };
+        let Some(file) = files.files().into_iter().next() else {
+            return;
+        };
+        let Some(contents) = files.read_file_to_string(&file).await else {
+            status.set(format!("Unable to read {}", file));
+            return;
+        };
+
+        match import::preview(&contents).await {
+            Ok(new_preview) => {
+                status.set(format!(
+                    "{} accounts ready to import, {} rows skipped",
+                    new_preview.ready().len(),
+                    new_preview.skipped().len()
+                ));
+                preview.set(Some(new_preview));
+            }
+            Err(e) => status.set(e.to_string()),
+        }
+    };
+
+    let apply = move || async move {
+        let Some(current) = preview() else {
+            return;
+        };
+        match import::apply(&current).await {
+            Ok(imported) => {
+                let skipped = current
+                    .skipped()
+                    .into_iter()
+                    .map(|(line, reason)| format!("line {}: {}", line, reason))
+                    .collect::<Vec<_>>();
+                status.set(if skipped.is_empty() {
+                    format!("Imported {} accounts", imported)
+                } else {
+                    format!(
+                        "Imported {} accounts, skipped {}",
+                        imported,
+                        skipped.join("; ")
+                    )
+                });
+                preview.set(None);
+            }
+            Err(e) => status.set(format!("Import failed, nothing was imported: {}", e)),
+        }
+    };
+
+    let columns = preview()
+        .map(|preview| {
+            preview
+                .columns
+                .iter()
+                .map(|(field, header)| format!("{:?} \u{2190} \"{}\"", field, header))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .unwrap_or_default();
+
+    let row_elements = preview()
+        .into_iter()
+        .flat_map(|preview| preview.rows)
+        .map(|row| {
+            let (class, id, name, credit, bunk, note) = match (&row.account, &row.duplicate) {
+                (Ok(account), None) => (
+                    "",
+                    account.id.to_string(),
+                    account.name.clone(),
+                    format!("${:.02}", account.credit as f32 / 100.0),
+                    account.bunk.to_string(),
+                    "OK".to_string(),
+                ),
+                (Ok(account), Some(duplicate)) => (
+                    "text-warning",
+                    // rows without an id only get one if they're imported
+                    Some(account.id)
+                        .filter(|&id| id != 0)
+                        .map(|id| id.to_string())
+                        .unwrap_or_default(),
+                    account.name.clone(),
+                    format!("${:.02}", account.credit as f32 / 100.0),
+                    account.bunk.to_string(),
+                    duplicate.clone(),
+                ),
+                (Err(reason), _) => (
+                    "text-error",
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    reason.clone(),
+                ),
+            };
+
+            rsx! {
+                tr {
+                    key: "{row.line}",
+                    class: class,
+                    td { {row.line.to_string()} }
+                    td { {id} }
+                    td { {name} }
+                    td { {credit} }
+                    td { {bunk} }
+                    td { {note} }
+                }
+            }
+        });
+
+    let ready = preview()
+        .map(|preview| preview.ready().len())
+        .unwrap_or_default();
+
+    rsx! {
+        div {
+            class: "grow flex flex-col gap-2 p-2 rounded-md bg-base-200",
+            div {
+                class: "flex w-full gap-2 justify-center",
+                input {
+                    r#type: "file",
+                    accept: ".csv",
+                    class: "file-input file-input-sm",
+                    onchange: load_file,
+                }
+            }
+            div { class: "text-sm text-center", {columns} }
+            div {
+                class: "grow overflow-y-auto w-full",
+                table {
+                    class: "table table-sm",
+                    thead {
+                        tr {
+                            th { "Line" }
+                            th { "Id" }
+                            th { "Name" }
+                            th { "Credit" }
+                            th { "Bunk" }
+                            th { "Status" }
+                        }
+                    }
+                    tbody { {row_elements} }
+                }
+            }
+            div {
+                class: "text-center",
+                {status}
+            }
+            div {
+                class: "flex gap-2 justify-center",
+                button {
+                    class: "btn btn-success",
+                    disabled: ready == 0,
+                    onclick: move |_| apply(),
+                    {format!("Import {} Accounts", ready)}
+                }
+                button {
+                    class: "btn btn-error",
+                    onclick: move |_| form_setter.set(Form::Accounts),
+                    "Back"
+                }
+            }
+        }
+    }
+}
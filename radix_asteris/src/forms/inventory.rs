@@ -1,8 +1,489 @@
+use std::collections::BTreeMap;
+
 use dioxus::prelude::*;
+use model::{Item, StockReason, StockUpdate};
+
+use crate::{database, export::dollars, import::parse_amount};
 
 use super::Form;
 
+// the category filter's value for items without one
+const UNCATEGORIZED: &str = "\u{0}uncategorized";
+
 #[component]
 pub fn Inventory(form_setter: Signal<Form>) -> Element {
-    rsx! {}
+    let mut status = use_signal(|| String::new());
+    // edit flag per item so saving only touches what changed
+    let mut items: Signal<BTreeMap<u32, (bool, Item)>> = use_signal(|| BTreeMap::new());
+    let mut categories: Signal<Vec<String>> = use_signal(|| Vec::new());
+    let mut category_filter: Signal<Option<String>> = use_signal(|| None);
+    let mut rename_from = use_signal(|| String::new());
+    let mut rename_to = use_signal(|| String::new());
+    let mut new_item = use_signal(|| Item {
+        id: 0,
+        name: String::new(),
+        gtin: None,
+        price: 0,
+        category: None,
+        stock: None,
+        low_stock: None,
+        unit_cost: None,
+    });
+    let mut new_price = use_signal(|| String::new());
+    let mut movement = use_signal(|| StockUpdate {
+        item_id: 0,
+        quantity: 0,
+        reason: StockReason::Receive,
+        note: None,
+    });
+
+    let load = move || async move {
+        match database::get_all_items().await {
+            Ok(all) => items.set(
+                all.into_iter()
+                    .map(|item| (item.id, (false, item)))
+                    .collect(),
+            ),
+            Err(e) => status.set(e.to_string()),
+        }
+        match database::get_categories().await {
+            Ok(all) => categories.set(all),
+            Err(e) => status.set(e.to_string()),
+        }
+    };
+
+    use_future(load);
+
+    let save = move || async move {
+        let edited = items()
+            .into_values()
+            .filter(|(edited, _)| *edited)
+            .map(|(_, item)| item)
+            .collect::<Vec<_>>();
+        for item in &edited {
+            if let Err(e) = database::insert_item(item.clone()).await {
+                status.set(e.to_string());
+                return;
+            }
+        }
+        status.set(format!("Saved {} items", edited.len()));
+        load().await;
+    };
+
+    let add_item = move || async move {
+        let mut item = new_item();
+        if item.id == 0 || item.name.trim().is_empty() {
+            status.set("A new item needs a number above 0 and a name".to_string());
+            return;
+        }
+        if items().contains_key(&item.id) {
+            status.set(format!("Item {} already exists", item.id));
+            return;
+        }
+        let Some(price) = parse_amount(&new_price()).and_then(|cents| u32::try_from(cents).ok())
+        else {
+            status.set(format!("Invalid price \"{}\"", new_price()));
+            return;
+        };
+        item.price = price;
+        match database::insert_item(item.clone()).await {
+            Ok(()) => {
+                status.set(format!("Added {}", item.name));
+                new_item.set(Item {
+                    id: 0,
+                    name: String::new(),
+                    gtin: None,
+                    price: 0,
+                    category: item.category,
+                    stock: None,
+                    low_stock: None,
+                    unit_cost: None,
+                });
+                new_price.set(String::new());
+            }
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let remove_item = move |id: u32| async move {
+        match database::remove_item(id).await {
+            Ok(()) => status.set(format!("Removed item {}", id)),
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    // a recount, typed straight into the Stock column, blank to stop counting the item
+    let recount = move |id: u32, value: String| async move {
+        let result = match value.trim() {
+            "" => database::clear_stock(id)
+                .await
+                .map(|()| format!("Stopped counting item {}", id)),
+            count => match count.parse::<i32>() {
+                Ok(count) => database::update_stock(StockUpdate {
+                    item_id: id,
+                    quantity: count,
+                    reason: StockReason::Adjust,
+                    note: None,
+                })
+                .await
+                .map(|item| format!("Counted {} {}", count, item.name)),
+                Err(_) => Ok(format!("Invalid count \"{}\"", count)),
+            },
+        };
+        match result {
+            Ok(message) => status.set(message),
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let record_movement = move || async move {
+        let update = movement();
+        if !items().contains_key(&update.item_id) {
+            status.set(format!("No item {}", update.item_id));
+            return;
+        }
+        match database::update_stock(update.clone()).await {
+            Ok(item) => {
+                status.set(format!(
+                    "{} {} of {}, {} on hand",
+                    update.reason,
+                    update.quantity,
+                    item.name,
+                    item.stock.unwrap_or_default()
+                ));
+                movement.set(StockUpdate {
+                    item_id: 0,
+                    quantity: 0,
+                    reason: update.reason,
+                    note: None,
+                });
+            }
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let rename_category = move || async move {
+        if rename_from().is_empty() {
+            status.set("Pick a category to rename".to_string());
+            return;
+        }
+        match database::rename_category(&rename_from(), &rename_to()).await {
+            Ok(moved) if rename_to().trim().is_empty() => {
+                status.set(format!("Took {} items out of {}", moved, rename_from()))
+            }
+            Ok(moved) => status.set(format!(
+                "Moved {} items from {} to {}",
+                moved,
+                rename_from(),
+                rename_to().trim()
+            )),
+            Err(e) => status.set(e.to_string()),
+        }
+        rename_from.set(String::new());
+        rename_to.set(String::new());
+        category_filter.set(None);
+        load().await;
+    };
+
+    let item_elements = items()
+        .into_iter()
+        .filter(|(_, (_, item))| match category_filter().as_deref() {
+            None => true,
+            Some(UNCATEGORIZED) => item.category.is_none(),
+            Some(filter) => item.category.as_deref() == Some(filter),
+        })
+        .map(|(id, (_, item))| {
+            rsx! {
+                tr {
+                    key: "{id}",
+                    td { {id.to_string()} }
+                    td {
+                        input {
+                            class: "input input-sm",
+                            value: item.name.clone(),
+                            oninput: move |i| {
+                                if let Some((edited, item)) = items.write().get_mut(&id) {
+                                    *edited = true;
+                                    item.name = i.value();
+                                }
+                            }
+                        }
+                    }
+                    td {
+                        input {
+                            class: "input input-sm w-36",
+                            placeholder: "None",
+                            value: item.gtin.map(|gtin| gtin.to_string()).unwrap_or_default(),
+                            oninput: move |i| {
+                                let value = i.value();
+                                if value.chars().all(|c| c.is_ascii_digit()) {
+                                    if let Some((edited, item)) = items.write().get_mut(&id) {
+                                        *edited = true;
+                                        item.gtin = value.parse::<u32>().ok();
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    td {
+                        // applied when the field loses focus, "1." isn't a price yet
+                        input {
+                            class: "input input-sm w-24",
+                            value: dollars(item.price as i64),
+                            onchange: move |i| {
+                                let value = i.value();
+                                match parse_amount(&value).and_then(|cents| u32::try_from(cents).ok()) {
+                                    Some(price) => {
+                                        if let Some((edited, item)) = items.write().get_mut(&id) {
+                                            *edited = true;
+                                            item.price = price;
+                                        }
+                                    }
+                                    None => status.set(format!("Invalid price \"{}\"", value)),
+                                }
+                            }
+                        }
+                    }
+                    td {
+                        // blank for items we don't know the cost of, receiving a delivery fills it in
+                        {item.unit_cost.map(|cost| format!("${}", dollars(cost as i64))).unwrap_or("—".to_string())}
+                    }
+                    td {
+                        input {
+                            class: "input input-sm w-36",
+                            list: "categories",
+                            placeholder: "None",
+                            value: item.category.clone().unwrap_or_default(),
+                            oninput: move |i| {
+                                let value = i.value();
+                                if let Some((edited, item)) = items.write().get_mut(&id) {
+                                    *edited = true;
+                                    item.category = Some(value).filter(|value| !value.trim().is_empty());
+                                }
+                            }
+                        }
+                    }
+                    td {
+                        // applied when the field loses focus, and recorded as a recount right away rather than on Save
+                        input {
+                            class: format!(
+                                "input input-sm w-20 {}",
+                                if item.out_of_stock() {
+                                    "input-error"
+                                } else if item.low_on_stock() {
+                                    "input-warning"
+                                } else {
+                                    ""
+                                }
+                            ),
+                            placeholder: "—",
+                            value: item.stock.map(|stock| stock.to_string()).unwrap_or_default(),
+                            onchange: move |i| recount(id, i.value()),
+                        }
+                    }
+                    td {
+                        input {
+                            class: "input input-sm w-20",
+                            placeholder: "—",
+                            value: item.low_stock.map(|low| low.to_string()).unwrap_or_default(),
+                            oninput: move |i| {
+                                let value = i.value();
+                                if value.chars().all(|c| c.is_ascii_digit()) {
+                                    if let Some((edited, item)) = items.write().get_mut(&id) {
+                                        *edited = true;
+                                        item.low_stock = value.parse::<u32>().ok();
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    td {
+                        button {
+                            class: "btn btn-error btn-sm",
+                            onclick: move |_| remove_item(id),
+                            "Delete"
+                        }
+                    }
+                }
+            }
+        });
+
+    rsx! {
+        div {
+            class: "grow flex flex-col gap-2 p-2 rounded-md bg-base-200",
+            datalist {
+                id: "categories",
+                for category in categories() {
+                    option { value: category }
+                }
+            }
+            div {
+                class: "flex w-full gap-2 justify-center items-center",
+                input {
+                    class: "input input-sm w-20",
+                    placeholder: "Number",
+                    value: if new_item().id == 0 { String::new() } else { new_item().id.to_string() },
+                    oninput: move |i| new_item.write().id = i.value().trim().parse().unwrap_or_default(),
+                }
+                input {
+                    class: "input input-sm w-48",
+                    placeholder: "Name",
+                    value: new_item().name,
+                    oninput: move |i| new_item.write().name = i.value(),
+                }
+                input {
+                    class: "input input-sm w-36",
+                    placeholder: "GTIN",
+                    value: new_item().gtin.map(|gtin| gtin.to_string()).unwrap_or_default(),
+                    oninput: move |i| new_item.write().gtin = i.value().trim().parse().ok(),
+                }
+                input {
+                    class: "input input-sm w-24",
+                    placeholder: "Price $",
+                    value: new_price,
+                    oninput: move |i| new_price.set(i.value()),
+                }
+                input {
+                    class: "input input-sm w-36",
+                    list: "categories",
+                    placeholder: "Category",
+                    value: new_item().category.unwrap_or_default(),
+                    oninput: move |i| new_item.write().category = Some(i.value()).filter(|value| !value.trim().is_empty()),
+                }
+                button {
+                    class: "btn btn-primary btn-sm",
+                    onclick: move |_| add_item(),
+                    "Add Item"
+                }
+            }
+            div {
+                class: "flex w-full gap-2 justify-center items-center",
+                select {
+                    class: "select select-sm w-48",
+                    onchange: move |e| {
+                        let value = e.value();
+                        category_filter.set(Some(value).filter(|value| !value.is_empty()));
+                    },
+                    option { value: "", selected: category_filter().is_none(), "All categories" }
+                    for category in categories() {
+                        option {
+                            value: category.clone(),
+                            selected: category_filter().as_ref() == Some(&category),
+                            {category.clone()}
+                        }
+                    }
+                    option { value: UNCATEGORIZED, selected: category_filter().as_deref() == Some(UNCATEGORIZED), "Uncategorized" }
+                }
+                "Rename"
+                select {
+                    class: "select select-sm w-48",
+                    onchange: move |e| rename_from.set(e.value()),
+                    option { value: "", selected: rename_from().is_empty(), "Category..." }
+                    for category in categories() {
+                        option {
+                            value: category.clone(),
+                            selected: rename_from() == category,
+                            {category.clone()}
+                        }
+                    }
+                }
+                "to"
+                input {
+                    class: "input input-sm w-48",
+                    placeholder: "New name, blank to clear",
+                    value: rename_to,
+                    oninput: move |i| rename_to.set(i.value()),
+                }
+                button {
+                    class: "btn btn-secondary btn-sm",
+                    onclick: move |_| rename_category(),
+                    "Rename"
+                }
+            }
+            div {
+                class: "flex w-full gap-2 justify-center items-center",
+                select {
+                    class: "select select-sm w-32",
+                    onchange: move |e| {
+                        movement.write().reason = match e.value().as_str() {
+                            "Waste" => StockReason::Waste,
+                            "Adjust" => StockReason::Adjust,
+                            _ => StockReason::Receive,
+                        }
+                    },
+                    for reason in [StockReason::Receive, StockReason::Waste, StockReason::Adjust] {
+                        option {
+                            value: reason.to_string(),
+                            selected: movement().reason == reason,
+                            {reason.to_string()}
+                        }
+                    }
+                }
+                input {
+                    class: "input input-sm w-20",
+                    placeholder: "Item",
+                    value: if movement().item_id == 0 { String::new() } else { movement().item_id.to_string() },
+                    oninput: move |i| movement.write().item_id = i.value().trim().parse().unwrap_or_default(),
+                }
+                input {
+                    class: "input input-sm w-20",
+                    placeholder: if movement().reason == StockReason::Adjust { "Count" } else { "Qty" },
+                    value: if movement().quantity == 0 { String::new() } else { movement().quantity.to_string() },
+                    oninput: move |i| movement.write().quantity = i.value().trim().parse().unwrap_or_default(),
+                }
+                input {
+                    class: "input input-sm w-48",
+                    placeholder: "Note",
+                    value: movement().note.unwrap_or_default(),
+                    oninput: move |i| movement.write().note = Some(i.value()).filter(|note| !note.trim().is_empty()),
+                }
+                button {
+                    class: "btn btn-secondary btn-sm",
+                    onclick: move |_| record_movement(),
+                    "Record"
+                }
+            }
+            div {
+                class: "grow overflow-y-auto w-full",
+                table {
+                    class: "table table-sm",
+                    thead {
+                        tr {
+                            th { "Number" }
+                            th { "Name" }
+                            th { "GTIN" }
+                            th { "Price" }
+                            th { "Unit Cost" }
+                            th { "Category" }
+                            th { "Stock" }
+                            th { "Low At" }
+                            th {}
+                        }
+                    }
+                    tbody { {item_elements} }
+                }
+            }
+            div {
+                class: "text-center",
+                {status}
+            }
+            div {
+                class: "flex gap-2 justify-center",
+                button {
+                    class: "btn btn-success",
+                    onclick: move |_| save(),
+                    "Save"
+                }
+                button {
+                    class: "btn btn-error",
+                    onclick: move |_| load(),
+                    "Discard"
+                }
+            }
+        }
+    }
 }
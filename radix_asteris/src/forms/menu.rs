@@ -1,5 +1,7 @@
 use dioxus::prelude::*;
 
+use crate::config::CONFIG;
+
 use super::Form;
 
 #[component]
@@ -13,20 +15,45 @@ pub fn Menu(form_setter: Signal<Form>) -> Element {
                 onclick: move |_| form_setter.set(Form::Accounts),
                 "Accounts"
             }
+            button {
+                class: "btn btn-primary",
+                onclick: move |_| form_setter.set(Form::Bunks),
+                "Bunks"
+            }
             button {
                 class: "btn btn-primary",
                 onclick: move |_| form_setter.set(Form::Inventory),
                 "Inventory"
             }
+            button {
+                class: "btn btn-primary",
+                onclick: move |_| form_setter.set(Form::Receiving),
+                "Receiving"
+            }
             button {
                 class: "btn btn-primary",
                 onclick: move |_| form_setter.set(Form::Sql),
                 "SQL"
             }
+            button {
+                class: "btn btn-primary",
+                onclick: move |_| form_setter.set(Form::Backups),
+                "Backups"
+            }
+            button {
+                class: "btn btn-primary",
+                onclick: move |_| form_setter.set(Form::Export),
+                "Export"
+            }
+            button {
+                class: "btn btn-primary",
+                onclick: move |_| form_setter.set(Form::Closeout),
+                "Closeout"
+            }
             button {
                 class: "btn btn-primary",
                 onclick: move |_| {
-                    if let Err(e) = open::that("http://localhost:5555/swagger-ui") {
+                    if let Err(e) = open::that(format!("http://localhost:{}/swagger-ui", CONFIG.port)) {
                         status.set(format!("Failed to open swagger-ui doc site: {}", e));
                     }
                 },
@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+
+use dioxus::prelude::*;
+use model::{Delivery, DeliveryLine, Item, ItemCost};
+
+use crate::{database, export::dollars, import::parse_amount};
+
+use super::Form;
+
+#[component]
+pub fn Receiving(form_setter: Signal<Form>) -> Element {
+    let mut status = use_signal(|| String::new());
+    let mut deliveries: Signal<Vec<Delivery>> = use_signal(|| Vec::new());
+    let mut items: Signal<BTreeMap<u32, Item>> = use_signal(|| BTreeMap::new());
+    let mut costs: Signal<Vec<ItemCost>> = use_signal(|| Vec::new());
+    // the selected delivery, edited here and only written back on Save
+    let mut draft: Signal<Option<Delivery>> = use_signal(|| None);
+    let mut new_supplier = use_signal(|| String::new());
+    let mut line_item = use_signal(|| String::new());
+    let mut line_quantity = use_signal(|| String::new());
+    let mut line_cost = use_signal(|| String::new());
+
+    let load = move || async move {
+        match database::get_deliveries().await {
+            Ok(all) => {
+                // pick the selected delivery up again as saved
+                let selected = draft().map(|delivery| delivery.id);
+                draft.set(selected.and_then(|id| all.iter().find(|d| d.id == id).cloned()));
+                deliveries.set(all);
+            }
+            Err(e) => status.set(e.to_string()),
+        }
+        match database::get_all_items().await {
+            Ok(all) => items.set(all.into_iter().map(|item| (item.id, item)).collect()),
+            Err(e) => status.set(e.to_string()),
+        }
+        match database::get_item_costs(None).await {
+            Ok(all) => costs.set(all),
+            Err(e) => status.set(e.to_string()),
+        }
+    };
+
+    use_future(load);
+
+    let new_delivery = move || async move {
+        let supplier = new_supplier().trim().to_string();
+        if supplier.is_empty() {
+            status.set("A new delivery needs a supplier".to_string());
+            return;
+        }
+        match database::create_delivery(&supplier, None).await {
+            Ok(id) => {
+                status.set(format!("Started delivery {} from {}", id, supplier));
+                new_supplier.set(String::new());
+                load().await;
+                draft.set(deliveries().into_iter().find(|delivery| delivery.id == id));
+            }
+            Err(e) => status.set(e.to_string()),
+        }
+    };
+
+    let mut add_line = move || {
+        let Some(item_id) = line_item()
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|id| items().contains_key(id))
+        else {
+            status.set(format!("No item \"{}\"", line_item()));
+            return;
+        };
+        let Some(quantity) = line_quantity()
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|&q| q > 0)
+        else {
+            status.set(format!("Invalid quantity \"{}\"", line_quantity()));
+            return;
+        };
+        let Some(unit_cost) =
+            parse_amount(&line_cost()).and_then(|cents| u32::try_from(cents).ok())
+        else {
+            status.set(format!("Invalid cost \"{}\"", line_cost()));
+            return;
+        };
+        if let Some(delivery) = draft.write().as_mut() {
+            delivery.lines.push(DeliveryLine {
+                item_id,
+                quantity,
+                unit_cost,
+            });
+        }
+        line_item.set(String::new());
+        line_quantity.set(String::new());
+        line_cost.set(String::new());
+        status.set(String::new());
+    };
+
+    let save = move || async move {
+        let Some(delivery) = draft() else {
+            return;
+        };
+        match database::save_delivery(&delivery).await {
+            Ok(()) => status.set(format!("Saved delivery {}", delivery.id)),
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let receive = move || async move {
+        let Some(delivery) = draft() else {
+            return;
+        };
+        // receive what's on screen, not what was last saved
+        let result = match database::save_delivery(&delivery).await {
+            Ok(()) => database::receive_delivery(delivery.id).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(lines) => status.set(format!(
+                "Received delivery {}, {} lines added to stock",
+                delivery.id, lines
+            )),
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let remove = move || async move {
+        let Some(delivery) = draft() else {
+            return;
+        };
+        match database::remove_delivery(delivery.id).await {
+            Ok(()) => {
+                status.set(format!("Deleted delivery {}", delivery.id));
+                draft.set(None);
+            }
+            Err(e) => status.set(e.to_string()),
+        }
+        load().await;
+    };
+
+    let item_name = move |id: u32| {
+        items()
+            .get(&id)
+            .map(|item| item.name.clone())
+            .unwrap_or(format!("Item {}", id))
+    };
+
+    // what the item in the line being entered cost before, newest first
+    let line_history = line_item()
+        .trim()
+        .parse::<u32>()
+        .map(|id| {
+            costs()
+                .into_iter()
+                .rev()
+                .filter(|cost| cost.item_id == id)
+                .take(5)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let delivery_elements = deliveries().into_iter().map(|delivery| {
+        let id = delivery.id;
+        let selected = draft().is_some_and(|draft| draft.id == id);
+        rsx! {
+            button {
+                key: "{id}",
+                class: format!("btn btn-sm justify-between {}", if selected { "btn-primary" } else { "" }),
+                onclick: move |_| draft.set(deliveries().into_iter().find(|delivery| delivery.id == id)),
+                span { {format!("#{} {}", id, delivery.supplier)} }
+                span {
+                    class: "text-xs",
+                    {match &delivery.received_at {
+                        Some(received_at) => received_at.chars().take(10).collect::<String>(),
+                        None => "Draft".to_string(),
+                    }}
+                }
+            }
+        }
+    });
+
+    let editor = match draft() {
+        None => rsx! {
+            div { class: "m-auto", "Pick a delivery, or start a new one" }
+        },
+        Some(delivery) => {
+            let received = delivery.received_at.is_some();
+            let total = delivery
+                .lines
+                .iter()
+                .map(|line| line.quantity as i64 * line.unit_cost as i64)
+                .sum::<i64>();
+            let line_elements = delivery.lines.iter().cloned().enumerate().map(|(index, line)| {
+                rsx! {
+                    tr {
+                        key: "{index}",
+                        td { {line.item_id.to_string()} }
+                        td { {item_name(line.item_id)} }
+                        td { {line.quantity.to_string()} }
+                        td { {format!("${}", dollars(line.unit_cost as i64))} }
+                        td { {format!("${}", dollars(line.quantity as i64 * line.unit_cost as i64))} }
+                        td {
+                            button {
+                                class: "btn btn-error btn-xs",
+                                disabled: received,
+                                onclick: move |_| {
+                                    if let Some(delivery) = draft.write().as_mut() {
+                                        delivery.lines.remove(index);
+                                    }
+                                },
+                                "Remove"
+                            }
+                        }
+                    }
+                }
+            });
+            rsx! {
+                div {
+                    class: "flex w-full gap-2 items-center",
+                    input {
+                        class: "input input-sm w-48",
+                        placeholder: "Supplier",
+                        disabled: received,
+                        value: delivery.supplier.clone(),
+                        oninput: move |i| {
+                            if let Some(delivery) = draft.write().as_mut() {
+                                delivery.supplier = i.value();
+                            }
+                        }
+                    }
+                    input {
+                        class: "input input-sm grow",
+                        placeholder: "Note, e.g. invoice number",
+                        disabled: received,
+                        value: delivery.note.clone().unwrap_or_default(),
+                        oninput: move |i| {
+                            if let Some(delivery) = draft.write().as_mut() {
+                                delivery.note = Some(i.value()).filter(|note| !note.trim().is_empty());
+                            }
+                        }
+                    }
+                    div {
+                        class: "text-sm",
+                        {match &delivery.received_at {
+                            Some(received_at) => format!("Received {}", received_at),
+                            None => format!("Started {}", delivery.created_at),
+                        }}
+                    }
+                }
+                div {
+                    class: "grow overflow-y-auto w-full",
+                    table {
+                        class: "table table-sm",
+                        thead {
+                            tr {
+                                th { "Number" }
+                                th { "Item" }
+                                th { "Quantity" }
+                                th { "Unit Cost" }
+                                th { "Cost" }
+                                th {}
+                            }
+                        }
+                        tbody { {line_elements} }
+                    }
+                }
+                div {
+                    class: "text-right text-lg",
+                    {format!("Total ${}", dollars(total))}
+                }
+                if !received {
+                    div {
+                        class: "flex w-full gap-2 justify-center items-center",
+                        input {
+                            class: "input input-sm w-24",
+                            placeholder: "Item",
+                            value: line_item,
+                            oninput: move |i| line_item.set(i.value()),
+                        }
+                        div {
+                            class: "w-48 truncate",
+                            {line_item().trim().parse::<u32>().ok().and_then(|id| items().get(&id).map(|item| item.name.clone())).unwrap_or_default()}
+                        }
+                        input {
+                            class: "input input-sm w-24",
+                            placeholder: "Quantity",
+                            value: line_quantity,
+                            oninput: move |i| line_quantity.set(i.value()),
+                        }
+                        input {
+                            class: "input input-sm w-24",
+                            placeholder: "Unit cost $",
+                            value: line_cost,
+                            oninput: move |i| line_cost.set(i.value()),
+                        }
+                        button {
+                            class: "btn btn-secondary btn-sm",
+                            onclick: move |_| add_line(),
+                            "Add Line"
+                        }
+                    }
+                    if !line_history.is_empty() {
+                        div {
+                            class: "flex flex-col items-center text-sm",
+                            for cost in line_history.clone() {
+                                div {
+                                    {format!(
+                                        "${} each for {} from {} on {}",
+                                        dollars(cost.unit_cost as i64),
+                                        cost.quantity,
+                                        cost.supplier,
+                                        cost.received_at.chars().take(10).collect::<String>()
+                                    )}
+                                }
+                            }
+                        }
+                    }
+                    div {
+                        class: "flex gap-2 justify-center",
+                        button {
+                            class: "btn btn-success",
+                            onclick: move |_| save(),
+                            "Save Draft"
+                        }
+                        button {
+                            class: "btn btn-primary",
+                            onclick: move |_| receive(),
+                            "Receive"
+                        }
+                        button {
+                            class: "btn btn-error",
+                            onclick: move |_| remove(),
+                            "Delete"
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    rsx! {
+        div {
+            class: "grow flex gap-2 p-2 rounded-md bg-base-200",
+            div {
+                class: "flex flex-col gap-2 w-1/4",
+                div {
+                    class: "flex gap-2",
+                    input {
+                        class: "input input-sm grow",
+                        placeholder: "Supplier",
+                        value: new_supplier,
+                        oninput: move |i| new_supplier.set(i.value()),
+                    }
+                    button {
+                        class: "btn btn-primary btn-sm",
+                        onclick: move |_| new_delivery(),
+                        "New Delivery"
+                    }
+                }
+                div {
+                    class: "flex flex-col gap-1 overflow-y-auto",
+                    {delivery_elements}
+                }
+            }
+            div {
+                class: "grow flex flex-col gap-2",
+                {editor}
+                div {
+                    class: "text-center",
+                    {status}
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use model::{Health, Readiness, ReadinessCheck};
+
+use crate::{config::CONFIG, database};
+
+// a database stuck behind a long-held lock is as good as unreachable to a register
+const DB_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Starts the uptime clock, called once the server is about to start listening.
+pub fn mark_started() {
+    LazyLock::force(&STARTED);
+}
+
+pub fn health() -> Health {
+    Health {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_secs: STARTED.elapsed().as_secs(),
+    }
+}
+
+pub async fn readiness() -> Readiness {
+    let checks = vec![
+        check("database", check_database().await),
+        check("schema", check_schema().await),
+        check("disk", check_disk()),
+    ];
+    let Health {
+        version,
+        uptime_secs,
+    } = health();
+
+    Readiness {
+        ready: checks.iter().all(|check| check.ok),
+        version,
+        uptime_secs,
+        checks,
+    }
+}
+
+fn check(name: &str, result: Result<(), String>) -> ReadinessCheck {
+    ReadinessCheck {
+        name: name.to_string(),
+        ok: result.is_ok(),
+        detail: result.err(),
+    }
+}
+
+async fn check_database() -> Result<(), String> {
+    tokio::time::timeout(DB_CHECK_TIMEOUT, database::ping())
+        .await
+        .map_err(|_| "timed out waiting for the database".to_string())?
+        .map_err(|e| e.to_string())
+}
+
+async fn check_schema() -> Result<(), String> {
+    let version = tokio::time::timeout(DB_CHECK_TIMEOUT, database::get_schema_version())
+        .await
+        .map_err(|_| "timed out waiting for the database".to_string())?
+        .map_err(|e| e.to_string())?;
+
+    if version == database::SCHEMA_VERSION {
+        Ok(())
+    } else {
+        Err(format!(
+            "schema is at version {}, expected {}",
+            version,
+            database::SCHEMA_VERSION
+        ))
+    }
+}
+
+// the database and its WAL live next to each other, so that's the directory that has to take writes
+fn check_disk() -> Result<(), String> {
+    let dir = CONFIG
+        .db_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(std::path::Path::new("."));
+    let probe = dir.join(".radix_asteris_ready");
+
+    std::fs::write(&probe, b"ok")
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| format!("{} is not writable: {}", dir.display(), e))
+}
@@ -0,0 +1,573 @@
+use std::{collections::HashMap, path::Path};
+
+use log::info;
+use model::Account;
+use thiserror::Error;
+
+use crate::database::{self, DBError};
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("Unable to read {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("Unable to parse CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("No name column found, expected a \"name\" column or \"first name\" and \"last name\"")]
+    NoNameColumn,
+    #[error("{0}")]
+    Database(#[from] DBError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    Id,
+    Name,
+    FirstName,
+    LastName,
+    Credit,
+    Overdraft,
+    Discount,
+    Bunk,
+    DailyLimit,
+    WeeklyLimit,
+    TransactionLimit,
+    Blocked,
+}
+
+impl Field {
+    // headers are compared lowercased with everything but letters and digits stripped, so "Camper Name" is "campername"
+    fn from_header(header: &str) -> Option<Self> {
+        let header = header
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .collect::<String>()
+            .to_lowercase();
+
+        match header.as_str() {
+            "id" | "camperid" | "accountid" | "account" => Some(Self::Id),
+            "name" | "campername" | "camper" | "fullname" => Some(Self::Name),
+            "firstname" | "first" => Some(Self::FirstName),
+            "lastname" | "last" | "surname" => Some(Self::LastName),
+            "credit" | "deposit" | "prepaid" | "balance" | "amount" => Some(Self::Credit),
+            "overdraft" => Some(Self::Overdraft),
+            "discount" => Some(Self::Discount),
+            "bunk" | "cabin" | "bunknumber" | "cabinnumber" => Some(Self::Bunk),
+            "dailylimit" | "perday" | "limitperday" => Some(Self::DailyLimit),
+            "weeklylimit" | "perweek" | "limitperweek" => Some(Self::WeeklyLimit),
+            "transactionlimit" | "salelimit" | "persale" | "limitpersale" => {
+                Some(Self::TransactionLimit)
+            }
+            "blocked" | "restricted" | "restrictions" | "blockeditems" => Some(Self::Blocked),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportRow {
+    /// Line in the file, the header being line 1
+    pub line: usize,
+    /// The account this row would create, or why it couldn't be read
+    pub account: Result<Account, String>,
+    /// Set when the account clashes with an existing one or an earlier row, such rows are skipped
+    pub duplicate: Option<String>,
+}
+
+impl ImportRow {
+    pub fn is_ready(&self) -> bool {
+        self.account.is_ok() && self.duplicate.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportPreview {
+    /// Which header each recognized column came from
+    pub columns: Vec<(Field, String)>,
+    pub rows: Vec<ImportRow>,
+}
+
+impl ImportPreview {
+    pub fn ready(&self) -> Vec<Account> {
+        self.rows
+            .iter()
+            .filter(|row| row.is_ready())
+            .filter_map(|row| row.account.clone().ok())
+            .collect()
+    }
+
+    /// Every row that won't be imported, with the reason.
+    pub fn skipped(&self) -> Vec<(usize, String)> {
+        self.rows
+            .iter()
+            .filter_map(|row| match (&row.account, &row.duplicate) {
+                (Err(reason), _) => Some((row.line, reason.clone())),
+                (Ok(_), Some(duplicate)) => Some((row.line, duplicate.clone())),
+                (Ok(_), None) => None,
+            })
+            .collect()
+    }
+}
+
+/// Parses a CSV of campers and checks every row against the existing accounts, without writing anything.
+pub async fn preview(contents: &str) -> Result<ImportPreview, ImportError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(contents.as_bytes());
+
+    let columns = reader
+        .headers()?
+        .iter()
+        .enumerate()
+        .filter_map(|(index, header)| {
+            Field::from_header(header).map(|field| (field, (index, header.to_string())))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let has_name = columns.contains_key(&Field::Name)
+        || (columns.contains_key(&Field::FirstName) && columns.contains_key(&Field::LastName));
+    if !has_name {
+        return Err(ImportError::NoNameColumn);
+    }
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let line = index + 2;
+        let account = match record {
+            Ok(record) => parse_row(&columns, &record),
+            Err(e) => Err(e.to_string()),
+        };
+        rows.push(ImportRow {
+            line,
+            account,
+            duplicate: None,
+        });
+    }
+
+    let existing = database::get_all_accounts().await?;
+    find_duplicates(&mut rows, &existing);
+    assign_ids(&mut rows, &existing);
+
+    let mut columns = columns
+        .into_iter()
+        .map(|(field, (index, header))| (index, field, header))
+        .collect::<Vec<_>>();
+    columns.sort_by_key(|(index, _, _)| *index);
+
+    Ok(ImportPreview {
+        columns: columns
+            .into_iter()
+            .map(|(_, field, header)| (field, header))
+            .collect(),
+        rows,
+    })
+}
+
+/// Inserts every ready row in one transaction, if any insert fails none of them are kept.
+pub async fn apply(preview: &ImportPreview) -> Result<usize, ImportError> {
+    let accounts = preview.ready();
+    database::insert_accounts(&accounts).await?;
+
+    info!(
+        "Imported {} accounts, skipped {} rows",
+        accounts.len(),
+        preview.skipped().len()
+    );
+    Ok(accounts.len())
+}
+
+fn parse_row(
+    columns: &HashMap<Field, (usize, String)>,
+    record: &csv::StringRecord,
+) -> Result<Account, String> {
+    let get = |field: Field| {
+        columns
+            .get(&field)
+            .and_then(|(index, _)| record.get(*index))
+            .unwrap_or_default()
+    };
+
+    let name = match get(Field::Name) {
+        "" => format!("{} {}", get(Field::FirstName), get(Field::LastName))
+            .trim()
+            .to_string(),
+        name => name.to_string(),
+    };
+    if name.is_empty() {
+        return Err("Missing name".to_string());
+    }
+
+    // 0 stands in until assign_ids hands out a real one
+    let id = match get(Field::Id) {
+        "" => 0,
+        id => id
+            .parse::<u32>()
+            .ok()
+            .filter(|&id| id > 0)
+            .ok_or(format!("Invalid id \"{}\"", id))?,
+    };
+
+    let (blocked_items, blocked_categories) = parse_blocked(get(Field::Blocked));
+
+    Ok(Account {
+        id,
+        name,
+        credit: parse_amount(get(Field::Credit))
+            .ok_or(format!("Invalid amount \"{}\"", get(Field::Credit)))?,
+        overdraft: parse_bool(get(Field::Overdraft))
+            .ok_or(format!("Invalid overdraft \"{}\"", get(Field::Overdraft)))?,
+        discount: match get(Field::Discount).trim_end_matches('%') {
+            "" => 0,
+            // a sale can't come off at more than its price
+            discount => discount
+                .parse()
+                .ok()
+                .filter(|&discount| discount <= 100)
+                .ok_or(format!("Invalid discount \"{}\"", get(Field::Discount)))?,
+        },
+        bunk: parse_bunk(get(Field::Bunk))
+            .ok_or(format!("Invalid bunk \"{}\"", get(Field::Bunk)))?,
+        locked: false,
+        daily_limit: parse_limit(get(Field::DailyLimit))?,
+        weekly_limit: parse_limit(get(Field::WeeklyLimit))?,
+        transaction_limit: parse_limit(get(Field::TransactionLimit))?,
+        blocked_items,
+        blocked_categories,
+    })
+}
+
+/// Dollars as written in a spreadsheet ("25", "$25.50", "1,000") to cents.
+pub fn parse_amount(amount: &str) -> Option<i32> {
+    let amount = amount.replace([',', '$'], "");
+    if amount.is_empty() {
+        return Some(0);
+    }
+
+    let (negative, amount) = match amount.strip_prefix('-') {
+        Some(amount) => (true, amount),
+        None => (false, amount.as_str()),
+    };
+    let (dollars, cents) = amount.split_once('.').unwrap_or((amount, ""));
+    if cents.len() > 2 || !cents.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let dollars = match dollars {
+        "" => 0,
+        dollars => dollars.parse::<i32>().ok()?,
+    };
+    let cents = format!("{:0<2}", cents).parse::<i32>().ok()?;
+    let total = dollars.checked_mul(100)?.checked_add(cents)?;
+    Some(if negative { -total } else { total })
+}
+
+// a blank limit is no limit at all, rather than a limit of $0
+fn parse_limit(limit: &str) -> Result<Option<u32>, String> {
+    if limit.is_empty() {
+        return Ok(None);
+    }
+    parse_amount(limit)
+        .and_then(|cents| u32::try_from(cents).ok())
+        .map(Some)
+        .ok_or(format!("Invalid limit \"{}\"", limit))
+}
+
+/// A list like "12, candy; 40" to blocked item ids and blocked categories, numbers being item ids.
+pub fn parse_blocked(blocked: &str) -> (Vec<u32>, Vec<String>) {
+    let mut items = Vec::new();
+    let mut categories = Vec::new();
+    for entry in blocked
+        .split([',', ';'])
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        match entry.parse::<u32>() {
+            Ok(id) => items.push(id),
+            Err(_) => categories.push(entry.to_string()),
+        }
+    }
+    items.sort();
+    items.dedup();
+    categories.sort_by_key(|category| category.to_lowercase());
+    categories.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+    (items, categories)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "" | "no" | "n" | "false" | "0" => Some(false),
+        "yes" | "y" | "true" | "1" | "x" => Some(true),
+        _ => None,
+    }
+}
+
+// cabins tend to be written "Cabin 7", only the number matters
+fn parse_bunk(bunk: &str) -> Option<u32> {
+    if bunk.is_empty() {
+        return Some(0);
+    }
+    bunk.chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+// ready rows without an id get the next free ones, after every id taken in the database or the file
+fn assign_ids(rows: &mut [ImportRow], existing: &[Account]) {
+    let mut next_id = existing
+        .iter()
+        .map(|account| account.id)
+        .chain(
+            rows.iter()
+                .filter_map(|row| row.account.as_ref().ok().map(|account| account.id)),
+        )
+        .max()
+        .unwrap_or_default()
+        + 1;
+
+    for account in rows
+        .iter_mut()
+        .filter(|row| row.duplicate.is_none())
+        .filter_map(|row| row.account.as_mut().ok())
+    {
+        if account.id == 0 {
+            account.id = next_id;
+            next_id += 1;
+        }
+    }
+}
+
+fn find_duplicates(rows: &mut [ImportRow], existing: &[Account]) {
+    let normalize = |name: &str| name.trim().to_lowercase();
+    let existing_ids = existing
+        .iter()
+        .map(|account| (account.id, account))
+        .collect::<HashMap<_, _>>();
+    let existing_names = existing
+        .iter()
+        .map(|account| (normalize(&account.name), account))
+        .collect::<HashMap<_, _>>();
+
+    let mut seen_ids = HashMap::new();
+    let mut seen_names = HashMap::new();
+    for row in rows.iter_mut() {
+        let Ok(account) = &row.account else {
+            continue;
+        };
+        let name = normalize(&account.name);
+
+        // rows without an id get a fresh one later, so only their names can clash
+        let id = Some(account.id).filter(|&id| id != 0);
+        row.duplicate = if let Some(existing) = id.and_then(|id| existing_ids.get(&id)) {
+            Some(format!(
+                "Id {} already belongs to {}",
+                account.id, existing.name
+            ))
+        } else if let Some(existing) = existing_names.get(&name) {
+            Some(format!(
+                "Already an account named {} (id {})",
+                existing.name, existing.id
+            ))
+        } else if let Some(line) = id.and_then(|id| seen_ids.get(&id)) {
+            Some(format!("Same id as line {}", line))
+        } else {
+            seen_names
+                .get(&name)
+                .map(|line| format!("Same name as line {}", line))
+        };
+
+        if let Some(id) = id {
+            seen_ids.entry(id).or_insert(row.line);
+        }
+        seen_names.entry(name).or_insert(row.line);
+    }
+}
+
+/// `radix_asteris import-accounts <file>`, prints the preview and asks before writing anything.
+pub async fn run_cli(path: &Path, yes: bool) -> Result<(), ImportError> {
+    database::init().await?;
+
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| ImportError::Io(path.display().to_string(), e))?;
+    let preview = preview(&contents).await?;
+
+    println!("Columns:");
+    for (field, header) in &preview.columns {
+        println!("  {:?} <- \"{}\"", field, header);
+    }
+
+    println!("\nLine  Id     Name                            Credit     Bunk  Status");
+    for row in &preview.rows {
+        match (&row.account, &row.duplicate) {
+            (Ok(account), duplicate) => println!(
+                "{:<5} {:<6} {:<31} {:<10} {:<5} {}",
+                row.line,
+                account.id,
+                account.name,
+                format!("{:.2}", account.credit as f32 / 100.0),
+                account.bunk,
+                duplicate.as_deref().unwrap_or("OK")
+            ),
+            (Err(reason), _) => println!("{:<5} {:<55} {}", row.line, "", reason),
+        }
+    }
+
+    let ready = preview.ready().len();
+    let skipped = preview.skipped().len();
+    println!(
+        "\n{} accounts ready to import, {} rows skipped",
+        ready, skipped
+    );
+    if ready == 0 {
+        return Ok(());
+    }
+
+    if !yes {
+        print!("Import {} accounts? [y/N] ", ready);
+        std::io::Write::flush(&mut std::io::stdout())
+            .map_err(|e| ImportError::Io("stdout".to_string(), e))?;
+        let mut input = String::new();
+        std::io::stdin()
+            .read_line(&mut input)
+            .map_err(|e| ImportError::Io("stdin".to_string(), e))?;
+        if !input.trim().eq_ignore_ascii_case("y") {
+            println!("Aborting import.");
+            return Ok(());
+        }
+    }
+
+    let imported = apply(&preview).await?;
+    println!("Imported {} accounts.", imported);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(id: u32, name: &str) -> Account {
+        Account {
+            id,
+            name: name.to_string(),
+            credit: 0,
+            overdraft: false,
+            discount: 0,
+            bunk: 0,
+            locked: false,
+            daily_limit: None,
+            weekly_limit: None,
+            transaction_limit: None,
+            blocked_items: Vec::new(),
+            blocked_categories: Vec::new(),
+        }
+    }
+
+    fn row(line: usize, id: u32, name: &str) -> ImportRow {
+        ImportRow {
+            line,
+            account: Ok(account(id, name)),
+            duplicate: None,
+        }
+    }
+
+    #[test]
+    fn amounts() {
+        assert_eq!(parse_amount(""), Some(0));
+        assert_eq!(parse_amount("25"), Some(2500));
+        assert_eq!(parse_amount("$25.50"), Some(2550));
+        assert_eq!(parse_amount("1,000"), Some(100000));
+        assert_eq!(parse_amount(".5"), Some(50));
+        assert_eq!(parse_amount("-3.05"), Some(-305));
+        assert_eq!(parse_amount("1.234"), None);
+        assert_eq!(parse_amount("ten"), None);
+        assert_eq!(parse_amount("99999999"), None);
+    }
+
+    #[test]
+    fn bunks() {
+        assert_eq!(parse_bunk(""), Some(0));
+        assert_eq!(parse_bunk("7"), Some(7));
+        assert_eq!(parse_bunk("Cabin 12"), Some(12));
+        assert_eq!(parse_bunk("Lakeside"), None);
+    }
+
+    #[test]
+    fn blocked() {
+        assert_eq!(parse_blocked(""), (vec![], vec![]));
+        assert_eq!(
+            parse_blocked("40, candy; 12, Candy, 12,  drinks"),
+            (
+                vec![12, 40],
+                vec!["candy".to_string(), "drinks".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn discount_over_100_is_refused() {
+        let columns = HashMap::from([
+            (Field::Name, (0, "name".to_string())),
+            (Field::Discount, (1, "discount".to_string())),
+        ]);
+        let parse = |discount: &str| {
+            parse_row(&columns, &csv::StringRecord::from(vec!["Sam", discount]))
+                .map(|account| account.discount)
+        };
+
+        assert_eq!(parse("25%"), Ok(25));
+        assert_eq!(parse("100"), Ok(100));
+        assert!(parse("250%").is_err());
+    }
+
+    #[test]
+    fn duplicates() {
+        let existing = [account(1, "Ada Lovelace")];
+        let mut rows = vec![
+            row(2, 1, "Someone Else"),
+            row(3, 0, " ada lovelace"),
+            row(4, 5, "Grace Hopper"),
+            row(5, 5, "Alan Turing"),
+            row(6, 0, "grace hopper"),
+            row(7, 0, "Edsger Dijkstra"),
+        ];
+        find_duplicates(&mut rows, &existing);
+
+        let duplicates = rows
+            .iter()
+            .map(|row| row.duplicate.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            duplicates,
+            [
+                Some("Id 1 already belongs to Ada Lovelace"),
+                Some("Already an account named Ada Lovelace (id 1)"),
+                None,
+                Some("Same id as line 4"),
+                Some("Same name as line 4"),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn ids_follow_the_highest_taken() {
+        let existing = [account(3, "Ada Lovelace")];
+        let mut rows = vec![
+            row(2, 0, "Grace Hopper"),
+            row(3, 8, "Alan Turing"),
+            ImportRow {
+                duplicate: Some("Same name as line 2".to_string()),
+                ..row(4, 0, "grace hopper")
+            },
+            row(5, 0, "Edsger Dijkstra"),
+        ];
+        assign_ids(&mut rows, &existing);
+
+        let ids = rows
+            .iter()
+            .map(|row| row.account.as_ref().unwrap().id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [9, 8, 0, 10]);
+    }
+}
@@ -0,0 +1,69 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::EnvFilter;
+
+use crate::config::CONFIG;
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// JSON lines into a file per day under `log_dir`, pruned to the newest `log_keep_days`.
+/// `RUST_LOG` takes precedence over the configured `log_level` when it's set.
+pub fn init() {
+    let _ = std::fs::create_dir_all(&CONFIG.log_dir);
+
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("radix_asteris")
+        .filename_suffix("log")
+        .max_log_files(CONFIG.log_keep_days)
+        .build(&CONFIG.log_dir)
+        .expect("Failed to initialize log file");
+
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&CONFIG.log_level))
+        .unwrap_or_else(|e| panic!("Malformed log level {}: {}", CONFIG.log_level, e));
+
+    // only fails if a subscriber is already installed, in which case that one keeps logging
+    let _ = tracing_subscriber::fmt()
+        .json()
+        .with_current_span(true)
+        .with_span_list(false)
+        .with_env_filter(filter)
+        .with_writer(appender)
+        .try_init();
+}
+
+/// Route layer giving each request an id, from the caller's `x-request-id` if it sent one.
+/// Everything logged while handling it, database calls included, carries the id, and it's echoed back on the response.
+pub async fn request_span(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        route = %route,
+    );
+
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(request_id) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+    }
+    response
+}
@@ -1,28 +1,83 @@
+pub mod backup;
+pub mod config;
 pub mod database;
+pub mod discovery;
+pub mod events;
+pub mod export;
+#[cfg(feature = "gui")]
 pub mod forms;
+pub mod health;
+pub mod import;
+pub mod logging;
+pub mod metrics;
+pub mod report;
 pub mod server;
+pub mod shutdown;
+pub mod statement;
 pub mod transaction;
 
-use clap::{Parser, arg, command};
-use dioxus::desktop::{Config, WindowBuilder};
+use std::{path::PathBuf, sync::LazyLock};
+
+use clap::{Parser, Subcommand, arg, command};
+use config::CONFIG;
+#[cfg(feature = "gui")]
+use dioxus::desktop::{Config, WindowBuilder, tao::event::Event};
+#[cfg(feature = "gui")]
 use forms::App;
-use log::{LevelFilter, info};
+use log::{error, info, warn};
 use utoipa::OpenApi;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use server::*;
 
+pub static ARGS: LazyLock<Args> = LazyLock::new(Args::parse);
+
+#[cfg(feature = "gui")]
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 #[derive(Parser, Debug)]
 #[command(version, about)]
-struct Args {
+pub struct Args {
     #[arg(short, long)]
     wipe: bool,
+    /// Run only the HTTP server, without the admin window
+    #[arg(long)]
+    headless: bool,
+    /// Path to the TOML config file
+    #[arg(short, long, default_value = "radix_asteris.toml")]
+    config: PathBuf,
+    /// Address to bind the HTTP server to, overrides the config file
+    #[arg(long)]
+    bind_address: Option<String>,
+    /// Port to bind the HTTP server to, overrides the config file
+    #[arg(short, long)]
+    port: Option<u16>,
+    /// Path to the SQLite database, overrides the config file
+    #[arg(long)]
+    db_path: Option<PathBuf>,
+    /// Directory to write logs to, overrides the config file
+    #[arg(long)]
+    log_dir: Option<PathBuf>,
+    /// Log level or filter (e.g. "debug" or "radix_asteris=debug,info"), overrides the config file
+    #[arg(long)]
+    log_level: Option<String>,
+    #[command(subcommand)]
+    action: Option<Action>,
 }
 
-async fn handle_args() {
-    let args = Args::parse();
+#[derive(Subcommand, Debug)]
+pub enum Action {
+    /// Create accounts from a CSV of campers, showing a preview before anything is written
+    ImportAccounts {
+        file: PathBuf,
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
 
-    if args.wipe {
+async fn handle_args() {
+    if ARGS.wipe {
         print!(
             "This will wipe all accounts, items, logs, the database and reinitialize everything, it should only be used to start COMPLETELY OVER.\n\nPlease type exactly \"Kill all data\" (without the quotes) to confirm: "
         );
@@ -40,81 +95,237 @@ async fn handle_args() {
 #[derive(OpenApi)]
 #[openapi(paths(
     default,
+    health,
+    ready,
+    metrics,
     transaction,
     get_accounts,
     get_account,
     insert_account,
     update_balance,
-    sync
+    sync,
+    events,
+    export_accounts,
+    export_history,
+    export_balance_changes,
+    account_statement,
+    bunk_statements,
+    get_bunks,
+    insert_bunk,
+    insert_item,
+    update_stock,
+    get_stock_movements,
+    get_deliveries,
+    get_item_costs,
+    get_categories,
+    update_bunk_balance,
+    bunk_report,
+    export_bunks,
+    margin_report,
+    export_margins
 ))]
 struct ApiDoc;
 
-pub async fn start_server() {
-    database::init()
-        .await
-        .expect("Failed to initialize database");
-
-    handle_args().await;
-
+/// Everything the registers talk to. Kept apart from the listener so it can be exercised on its own.
+pub fn router() -> axum::Router {
     let (router, _) = OpenApiRouter::new()
         .routes(routes!(default))
+        .routes(routes!(health))
+        .routes(routes!(ready))
+        .routes(routes!(metrics))
         .routes(routes!(transaction))
         .routes(routes!(get_accounts))
         .routes(routes!(get_account))
         .routes(routes!(insert_account))
         .routes(routes!(update_balance))
         .routes(routes!(sync))
+        .routes(routes!(events))
+        .routes(routes!(export_accounts))
+        .routes(routes!(export_history))
+        .routes(routes!(export_balance_changes))
+        .routes(routes!(account_statement))
+        .routes(routes!(bunk_statements))
+        .routes(routes!(get_bunks))
+        .routes(routes!(insert_bunk))
+        .routes(routes!(insert_item))
+        .routes(routes!(update_stock))
+        .routes(routes!(get_stock_movements))
+        .routes(routes!(get_deliveries))
+        .routes(routes!(get_item_costs))
+        .routes(routes!(get_categories))
+        .routes(routes!(update_bunk_balance))
+        .routes(routes!(bunk_report))
+        .routes(routes!(export_bunks))
+        .routes(routes!(margin_report))
+        .routes(routes!(export_margins))
         .split_for_parts();
 
-    let app = axum::Router::new().merge(router).merge(
+    // route_layer so the middleware runs after routing and can see which route matched
+    let router = router
+        .route_layer(axum::middleware::from_fn(metrics::track_requests))
+        .route_layer(axum::middleware::from_fn(logging::request_span));
+
+    axum::Router::new().merge(router).merge(
         utoipa_swagger_ui::SwaggerUi::new("/swagger-ui").url("/openapi.json", ApiDoc::openapi()),
-    );
+    )
+}
 
-    let listener = tokio::net::TcpListener::bind(("0.0.0.0", 5555))
+/// Runs the HTTP server until `shutdown` resolves, then stops accepting and lets in-flight requests finish.
+pub async fn start_server(shutdown: impl Future<Output = ()> + Send + 'static) {
+    database::init()
+        .await
+        .expect("Failed to initialize database");
+
+    handle_args().await;
+
+    let listener = tokio::net::TcpListener::bind((CONFIG.bind_address.as_str(), CONFIG.port))
         .await
         .unwrap();
-    axum::serve(listener, app.into_make_service())
+
+    if CONFIG.announce {
+        tokio::spawn(discovery::announce());
+    }
+    tokio::spawn(backup::run_schedule());
+    tokio::spawn(metrics::run_upkeep());
+
+    health::mark_started();
+    info!("Listening on {}:{}", CONFIG.bind_address, CONFIG.port);
+    axum::serve(listener, router().into_make_service())
+        .with_graceful_shutdown(shutdown)
         .await
         .unwrap();
-}
+    info!("Server stopped, all in-flight requests drained");
 
-fn init_log() {
-    let current_dir = std::env::current_dir().unwrap_or_default();
-    let current_dir = current_dir.to_str().unwrap_or_default();
-
-    let _ = std::fs::create_dir_all(format!("{}/logs", current_dir));
-
-    let _ = simplelog::WriteLogger::init(
-        LevelFilter::Info,
-        Default::default(),
-        std::fs::File::create(format!(
-            "{}/logs/{}.log",
-            current_dir,
-            chrono::Local::now().format("%Y%m%d_%H%M%S")
-        ))
-        .expect("Failed to initialize file"),
-    );
-
-    info!(
-        "Radix Asteris started on {}",
-        chrono::Local::now().format("%Y%m%d_%H%M%S")
-    );
+    if let Err(e) = database::checkpoint().await {
+        error!("Error checkpointing database on shutdown: {}", e);
+    }
+    shutdown::mark_finished();
 }
 
-fn main() {
-    init_log();
+/// Resolves on Ctrl+C, or SIGTERM where there is one (e.g. `systemctl stop`).
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Unable to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
 
-    let rt = tokio::runtime::Runtime::new().unwrap();
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Unable to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    rt.spawn(async {
-        start_server().await;
-    });
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    info!("Shutdown signal received");
+}
 
+#[cfg(feature = "gui")]
+pub fn launch_gui() {
     let window = WindowBuilder::new()
         .with_title("Radix Asteris")
         .with_resizable(true);
 
     dioxus::LaunchBuilder::desktop()
-        .with_cfg(Config::new().with_window(window).with_menu(None))
+        .with_cfg(
+            Config::new()
+                .with_window(window)
+                .with_menu(None)
+                .with_custom_event_handler(|event, _| {
+                    // the event loop exits the process right after this, so finish up the server first
+                    if let Event::LoopDestroyed = event {
+                        shutdown::request();
+                        shutdown::wait_finished(SHUTDOWN_TIMEOUT);
+                    }
+                }),
+        )
         .launch(App);
 }
+
+fn main() {
+    logging::init();
+    info!("Radix Asteris {} started", env!("CARGO_PKG_VERSION"));
+    for warning in config::load_warnings() {
+        warn!("{}", warning);
+    }
+    metrics::init();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+
+    if let Some(Action::ImportAccounts { file, yes }) = &ARGS.action {
+        if let Err(e) = rt.block_on(import::run_cli(file, *yes)) {
+            error!("Import failed: {}", e);
+            eprintln!("Import failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    #[cfg(feature = "gui")]
+    if !ARGS.headless {
+        // the admin window owns the process here, closing it shuts the server down with it
+        rt.spawn(start_server(shutdown::requested()));
+
+        launch_gui();
+        return;
+    }
+
+    info!("Running headless");
+    rt.spawn(async {
+        shutdown_signal().await;
+        shutdown::request();
+    });
+    rt.block_on(start_server(shutdown::requested()));
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use http_body_util::BodyExt;
+    use model::Health;
+    use tower::ServiceExt;
+
+    use super::router;
+
+    #[tokio::test]
+    async fn health_through_router() {
+        let response = router()
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let health: Health = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health.version, env!("CARGO_PKG_VERSION"));
+    }
+
+    #[tokio::test]
+    async fn transaction_without_token_is_unauthorized() {
+        let request = Request::post("/transaction")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"tx_id":"no-token","items":[{"id":1,"quantity":1}],"method":{"Cash":{"tender":100}}}"#,
+            ))
+            .unwrap();
+
+        let response = router().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use log::error;
+use metrics::{counter, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use model::TransactionStatus;
+
+use crate::transaction::TransactionError;
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+// requests and lock waits both land somewhere between well under a millisecond and a stalled lunch line
+const SECONDS_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Installs the global recorder, everything recorded before this is dropped.
+pub fn init() {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("seconds".to_string()), &SECONDS_BUCKETS)
+        .and_then(|builder| builder.install_recorder());
+
+    match handle {
+        Ok(handle) => {
+            let _ = HANDLE.set(handle);
+        }
+        Err(e) => error!("Unable to install metrics recorder: {}", e),
+    }
+}
+
+/// Histogram samples pile up between scrapes, this folds them into their buckets even if nothing scrapes us.
+pub async fn run_upkeep() {
+    let Some(handle) = HANDLE.get() else {
+        return;
+    };
+
+    loop {
+        tokio::time::sleep(UPKEEP_INTERVAL).await;
+        handle.run_upkeep();
+    }
+}
+
+pub fn render() -> String {
+    HANDLE
+        .get()
+        .map(PrometheusHandle::render)
+        .unwrap_or_default()
+}
+
+/// Route layer counting requests and timing them, labeled by route template rather than the literal path.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let elapsed = started.elapsed();
+
+    let status = response.status().as_u16().to_string();
+    counter!("http_requests_total", "method" => method.clone(), "route" => route.clone(), "status" => status)
+        .increment(1);
+    histogram!("http_request_duration_seconds", "method" => method, "route" => route)
+        .record(elapsed.as_secs_f64());
+
+    response
+}
+
+pub fn record_transaction(result: &Result<TransactionStatus, TransactionError>) {
+    let outcome = match result {
+        Ok(TransactionStatus::Success { .. }) => "success",
+        Ok(TransactionStatus::Partial { .. }) => "partial",
+        Ok(TransactionStatus::InvalidAccount { .. }) => "invalid_account",
+        Ok(TransactionStatus::PricesChanged { .. }) => "prices_changed",
+        Ok(TransactionStatus::AccountClosed { .. }) => "account_closed",
+        Ok(TransactionStatus::LimitExceeded { .. }) => "limit_exceeded",
+        Ok(TransactionStatus::ItemsBlocked { .. }) => "items_blocked",
+        Ok(TransactionStatus::OutOfStock { .. }) => "out_of_stock",
+        Ok(TransactionStatus::Failure { .. }) => "failure",
+        Err(TransactionError::Rejected(_)) => "rejected",
+        Err(TransactionError::Unauthorized) => "unauthorized",
+        Err(TransactionError::Internal(_)) => "error",
+    };
+    counter!("transactions_total", "outcome" => outcome).increment(1);
+}
+
+/// Called with how long a `DB` lock took to acquire, `contended` when someone else was holding it.
+pub fn record_db_lock_wait(wait: Duration, contended: bool) {
+    if contended {
+        counter!("db_lock_contended_total").increment(1);
+    }
+    histogram!("db_lock_wait_seconds").record(wait.as_secs_f64());
+}
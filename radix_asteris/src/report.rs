@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use model::{BalanceChangeReason, Bunk, BunkSpending, Margin, MarginReport};
+
+use crate::{
+    database::{self, DBError},
+    export::DateRange,
+};
+
+/// Spending and deposits per bunk, campers counted by the bunk they're in now.
+/// Campers without a bunk are grouped under bunk 0.
+pub async fn bunk_spending(range: &DateRange) -> Result<Vec<BunkSpending>, DBError> {
+    let accounts = database::get_all_accounts().await?;
+    let changes = database::get_balance_changes(range.from, range.to, None).await?;
+
+    let mut report = database::get_bunks()
+        .await?
+        .into_iter()
+        .map(|bunk| (bunk.id, spending(bunk)))
+        .collect::<HashMap<_, _>>();
+
+    let mut bunk_of = HashMap::new();
+    for account in accounts {
+        bunk_of.insert(account.id, account.bunk);
+        let entry = report.entry(account.bunk).or_insert_with(|| {
+            spending(Bunk {
+                id: account.bunk,
+                name: if account.bunk == 0 {
+                    "No bunk".to_string()
+                } else {
+                    format!("Bunk {}", account.bunk)
+                },
+                counselor: String::new(),
+            })
+        });
+        entry.campers += 1;
+        entry.balance += account.credit as i64;
+    }
+
+    // changes for accounts deleted since have nowhere to go
+    for change in changes {
+        let Some(entry) = bunk_of
+            .get(&change.account_id)
+            .and_then(|bunk| report.get_mut(bunk))
+        else {
+            continue;
+        };
+        match change.reason {
+            BalanceChangeReason::Purchase => {
+                entry.spent -= change.amount as i64;
+                entry.purchases += 1;
+            }
+            BalanceChangeReason::Deposit | BalanceChangeReason::Import => {
+                entry.deposited += change.amount as i64
+            }
+            _ => {}
+        }
+    }
+
+    let mut report = report.into_values().collect::<Vec<_>>();
+    report.sort_by_key(|spending| spending.bunk.id);
+    Ok(report)
+}
+
+fn spending(bunk: Bunk) -> BunkSpending {
+    BunkSpending {
+        bunk,
+        campers: 0,
+        balance: 0,
+        deposited: 0,
+        spent: 0,
+        purchases: 0,
+    }
+}
+
+/// Revenue, cost of goods and margin per item and per category. Credit sales count what the account
+/// actually paid, spread over the items by price. Sales recorded before costs were kept, and items
+/// without a cost when they sold, fall back to the item's unit cost now.
+pub async fn item_margins(range: &DateRange) -> Result<MarginReport, DBError> {
+    let items = database::get_all_items()
+        .await?
+        .into_iter()
+        .map(|item| (item.id, item))
+        .collect::<HashMap<_, _>>();
+    let history = database::get_history(range.from, range.to, None).await?;
+
+    let mut by_item: HashMap<u32, Margin> = HashMap::new();
+    for entry in history {
+        let current = |id: u32| items.get(&id);
+        let price = |id: u32| {
+            entry
+                .prices
+                .get(&id)
+                .copied()
+                .or(current(id).map(|item| item.price))
+                .unwrap_or_default() as i64
+        };
+        let listed = entry
+            .items
+            .iter()
+            .map(|line| price(line.id) * line.quantity as i64)
+            .sum::<i64>();
+        // what was taken for the whole sale, account discounts included
+        let paid = entry.total.map(|total| total as i64).unwrap_or(listed);
+
+        for line in &entry.items {
+            let row = by_item.entry(line.id).or_insert_with(|| Margin {
+                item_id: Some(line.id),
+                name: current(line.id)
+                    .map(|item| item.name.clone())
+                    .unwrap_or(format!("Item {}", line.id)),
+                category: current(line.id).and_then(|item| item.category.clone()),
+                ..margin("")
+            });
+            let revenue = if listed == 0 {
+                0
+            } else {
+                price(line.id) * line.quantity as i64 * paid / listed
+            };
+            row.quantity += line.quantity;
+            row.revenue += revenue;
+            match entry
+                .costs
+                .get(&line.id)
+                .copied()
+                .or(current(line.id).and_then(|item| item.unit_cost))
+            {
+                Some(unit_cost) => {
+                    let cost = unit_cost as i64 * line.quantity as i64;
+                    row.cost += cost;
+                    row.margin += revenue - cost;
+                }
+                None => row.uncosted += line.quantity,
+            }
+        }
+    }
+
+    let mut by_category: HashMap<Option<String>, Margin> = HashMap::new();
+    for item in by_item.values() {
+        let category = by_category
+            .entry(item.category.clone())
+            .or_insert_with(|| Margin {
+                category: item.category.clone(),
+                ..margin(item.category.as_deref().unwrap_or("Uncategorized"))
+            });
+        category.quantity += item.quantity;
+        category.uncosted += item.uncosted;
+        category.revenue += item.revenue;
+        category.cost += item.cost;
+        category.margin += item.margin;
+    }
+
+    let mut items = by_item.into_values().collect::<Vec<_>>();
+    items.sort_by(|a, b| b.revenue.cmp(&a.revenue).then(a.item_id.cmp(&b.item_id)));
+    let mut categories = by_category.into_values().collect::<Vec<_>>();
+    categories.sort_by(|a, b| b.revenue.cmp(&a.revenue).then(a.name.cmp(&b.name)));
+    Ok(MarginReport { items, categories })
+}
+
+fn margin(name: &str) -> Margin {
+    Margin {
+        item_id: None,
+        name: name.to_string(),
+        category: None,
+        quantity: 0,
+        uncosted: 0,
+        revenue: 0,
+        cost: 0,
+        margin: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::item_margins;
+    use crate::{database, export::DateRange};
+
+    // far enough back that nothing another test records lands in the range
+    async fn seed(id: &str, created_at: &str, items: &str, prices: &str, costs: &str, total: u32) {
+        database::generic_exec(&format!(
+            "INSERT INTO TransactionHistory (id, items, cash_back, created_at, total, prices, costs) VALUES ('{}', '{}', 0, '{}', {}, '{}', '{}')",
+            id, items, created_at, total, prices, costs
+        ))
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn margins_cover_both_ends_of_the_range() {
+        database::init().await.unwrap();
+        let one = r#"[{"id":9115,"quantity":1}]"#;
+        let prices = r#"{"9115":200}"#;
+        let costs = r#"{"9115":120}"#;
+        seed(
+            "report-9115-before",
+            "2001-05-31 23:59:59",
+            one,
+            prices,
+            costs,
+            200,
+        )
+        .await;
+        seed(
+            "report-9115-first",
+            "2001-06-01 00:00:00",
+            r#"[{"id":9115,"quantity":2}]"#,
+            prices,
+            costs,
+            400,
+        )
+        .await;
+        // a credit sale with a 10% discount, spread over both items by price
+        seed(
+            "report-9115-last",
+            "2001-06-02 23:59:59",
+            r#"[{"id":9115,"quantity":1},{"id":9116,"quantity":1}]"#,
+            r#"{"9115":200,"9116":100}"#,
+            costs,
+            270,
+        )
+        .await;
+        seed(
+            "report-9115-after",
+            "2001-06-03 00:00:00",
+            one,
+            prices,
+            costs,
+            200,
+        )
+        .await;
+
+        let report = item_margins(&DateRange {
+            from: NaiveDate::from_ymd_opt(2001, 6, 1),
+            to: NaiveDate::from_ymd_opt(2001, 6, 2),
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(report.items.len(), 2);
+        let costed = &report.items[0];
+        assert_eq!(costed.item_id, Some(9115));
+        assert_eq!(costed.quantity, 3);
+        assert_eq!(costed.revenue, 580);
+        assert_eq!(costed.cost, 360);
+        assert_eq!(costed.margin, 220);
+        assert_eq!(costed.uncosted, 0);
+
+        let uncosted = &report.items[1];
+        assert_eq!(uncosted.item_id, Some(9116));
+        assert_eq!(uncosted.revenue, 90);
+        assert_eq!(uncosted.uncosted, 1);
+
+        assert_eq!(report.categories.len(), 1);
+        let category = &report.categories[0];
+        assert_eq!(category.name, "Uncategorized");
+        assert_eq!(category.quantity, 4);
+        assert_eq!(category.revenue, 670);
+        assert_eq!(category.margin, 220);
+    }
+}
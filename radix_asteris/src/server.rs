@@ -1,14 +1,35 @@
 use std::{collections::HashMap, sync::LazyLock};
 
-use axum::{Json, extract::Path, http::HeaderMap, response::Html};
-use log::info;
+use axum::{
+    Json,
+    extract::{Path, Query},
+    http::{HeaderMap, StatusCode, header},
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::NaiveDate;
+use futures::Stream;
+use log::{info, warn};
 use model::{
-    Account, BalanceUpdate, SyncState, TransactionMethod, TransactionRequest, TransactionStatus,
+    Account, BalanceUpdate, Bunk, BunkBalanceUpdate, BunkSpending, Delivery, Health, Item,
+    ItemCost, MarginReport, Readiness, StockMovement, StockUpdate, SyncEvent, SyncKind, SyncState,
+    TransactionMethod, TransactionRejection, TransactionRequest, TransactionStatus,
 };
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::IntoParams;
 
 use crate::{
+    config::CONFIG,
     database,
-    transaction::{calc_total, handle_cash, handle_credit},
+    export::{self, DateRange, ExportFilter},
+    report, statement,
+    transaction::{
+        TransactionError, calc_total, check_stock, handle_cash, handle_credit, replay_partial,
+        replay_transaction, validate_items,
+    },
 };
 
 pub static AUTH_KEY: LazyLock<&str> = LazyLock::new(|| include_str!("../../.env").trim());
@@ -24,6 +45,50 @@ pub async fn default() -> Html<String> {
     Html("<!DOCTYPE html><html><head><title>get off my lawn</title></head><body><h1>This is not a website, please get out of my backend server</h1></body></html>".to_string())
 }
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, description = "The server process is up", body = Health),
+    ),
+)]
+pub async fn health() -> Json<Health> {
+    Json(crate::health::health())
+}
+
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+        (status = 200, description = "The server can take transactions", body = Readiness),
+        (status = 503, description = "At least one readiness check failed", body = Readiness),
+    ),
+)]
+pub async fn ready() -> (StatusCode, Json<Readiness>) {
+    let readiness = crate::health::readiness().await;
+    if !readiness.ready {
+        warn!("Readiness check failed: {:?}", readiness.checks);
+    }
+
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Prometheus text exposition of request, transaction and database metrics", body = String),
+    ),
+)]
+pub async fn metrics() -> String {
+    crate::metrics::render()
+}
+
 #[utoipa::path(
     post,
     path = "/transaction",
@@ -32,27 +97,106 @@ pub async fn default() -> Html<String> {
     ),
     responses(
         (status = 200, description = "Transaction Response", body = TransactionStatus),
+        (status = 401, description = "Missing or wrong authorization token", body = String),
+        (status = 422, description = "Transaction Rejected", body = TransactionRejection),
         (status = 500, description = "Transaction Error", body = String),
     ),
 )]
 pub async fn transaction(
     headers: HeaderMap,
     Json(payload): Json<TransactionRequest>,
-) -> Result<Json<TransactionStatus>, String> {
+) -> Result<Json<TransactionStatus>, TransactionError> {
     info!("Transaction request: {:?}", payload);
 
     if !check_auth(headers) {
-        return Err("Unauthorized".to_string());
+        return Err(TransactionError::Unauthorized);
     }
 
+    let result = process_transaction(payload).await;
+    crate::metrics::record_transaction(&result);
+    result.map(Json)
+}
+
+async fn process_transaction(
+    payload: TransactionRequest,
+) -> Result<TransactionStatus, TransactionError> {
     let TransactionRequest {
         tx_id,
         items,
         method,
+        pricebook_version,
+        remaining,
+        offline_total,
     } = payload;
 
-    let items: HashMap<u32, u32> = items.iter().map(|item| (item.id, item.quantity)).collect();
-    let total = calc_total(tx_id.clone(), items.clone()).await?;
+    if let Some(status) = replay_transaction(tx_id.clone()).await? {
+        info!(
+            "Transaction {} already completed, replaying: {:?}",
+            tx_id, status
+        );
+        return Ok(status);
+    }
+    if let Some(status) = replay_partial(tx_id.clone(), remaining).await {
+        info!(
+            "Transaction {} already partly paid, replaying: {:?}",
+            tx_id, status
+        );
+        return Ok(status);
+    }
+
+    let items = validate_items(items)
+        .await
+        .inspect_err(|e| info!("{}", e))?;
+    let mut total = calc_total(tx_id.clone(), items.clone()).await?;
+
+    // a partial transaction's remaining amount is already locked in, so drift only matters on a fresh one
+    let fresh = database::check_partial_transaction(tx_id.clone())
+        .await
+        .is_none();
+    let current_version = database::get_pricebook_version()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // a sale from a register's offline queue was settled at the prices the register had, which is
+    // fine as long as those were the server's prices at the version it says it had
+    if let Some(offline_total) = offline_total {
+        let version = pricebook_version.unwrap_or(current_version);
+        let expected = match version {
+            // a version the server hasn't reached yet, no register can have had it
+            version if version > current_version => None,
+            version => total_at(&items, version).await?,
+        };
+        if expected != Some(offline_total) {
+            info!(
+                "Offline sale {} came to {}, which doesn't match pricebook version {}",
+                tx_id, offline_total, version
+            );
+            return Ok(TransactionStatus::PricesChanged {
+                total,
+                pricebook_version: current_version,
+            });
+        }
+        total = offline_total;
+    } else if pricebook_version.is_some_and(|version| version != current_version) && fresh {
+        info!(
+            "Pricebook drift on {}: register has {}, server has {}",
+            tx_id,
+            pricebook_version.unwrap_or_default(),
+            current_version
+        );
+        return Ok(TransactionStatus::PricesChanged {
+            total,
+            pricebook_version: current_version,
+        });
+    }
+
+    // same goes for stock, once part of a sale has been paid for it goes through
+    if CONFIG.block_out_of_stock
+        && fresh
+        && let Some(status) = check_stock(&items).await?
+    {
+        return Ok(status);
+    }
 
     let result = match method {
         TransactionMethod::Cash { tender } => handle_cash(tx_id, tender, items, total).await,
@@ -61,7 +205,29 @@ pub async fn transaction(
         }
     };
 
-    result.map(Json)
+    result.map_err(TransactionError::from)
+}
+
+/// What the sale came to at a pricebook version, `None` if an item in it didn't exist yet.
+async fn total_at(items: &HashMap<u32, u32>, version: u64) -> Result<Option<u32>, String> {
+    let prices = database::get_prices_at(items.keys().copied().collect(), version)
+        .await
+        .map_err(|e| format!("Error looking up prices at version {}: {}", version, e))?;
+    Ok(items.iter().try_fold(0u32, |sum, (id, quantity)| {
+        sum.checked_add(prices.get(id)?.checked_mul(*quantity)?)
+    }))
+}
+
+impl IntoResponse for TransactionError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Rejected(rejection) => {
+                (StatusCode::UNPROCESSABLE_ENTITY, Json(rejection)).into_response()
+            }
+            Self::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()).into_response(),
+            Self::Internal(reason) => (StatusCode::INTERNAL_SERVER_ERROR, reason).into_response(),
+        }
+    }
 }
 
 #[utoipa::path(
@@ -169,38 +335,1121 @@ pub async fn update_balance(
     }
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SyncQuery {
+    /// Only return changes made after this sequence number. Omit (or pass 0) for a full sync.
+    since: Option<u64>,
+    /// Only return the pricebook or only the accounts. Omit for both.
+    only: Option<SyncKind>,
+}
+
 #[utoipa::path(
     get,
     path = "/sync",
     params(
         ("x-auth-token" = String, Header, description = "Authorization token"),
+        SyncQuery,
     ),
     responses(
         (status = 200, description = "Pricebook Response", body = SyncState),
         (status = 500, description = "Error querying items", body = String),
     ),
 )]
-pub async fn sync(headers: HeaderMap) -> Result<Json<SyncState>, String> {
-    info!("Sync request");
+pub async fn sync(
+    headers: HeaderMap,
+    Query(query): Query<SyncQuery>,
+) -> Result<Json<SyncState>, String> {
+    info!("Sync request: {:?}", query);
 
     if !check_auth(headers) {
         return Err("Unauthorized".to_string());
     }
 
-    let pricebook = database::get_all_items().await.map_err(|e| e.to_string())?;
-    let accounts = database::get_all_accounts()
+    // read the high-water mark first, anything changed while we gather rows just comes around again next time
+    let seq = database::get_sync_seq().await.map_err(|e| e.to_string())?;
+    let pricebook_version = database::get_pricebook_version()
         .await
         .map_err(|e| e.to_string())?;
+    let since = query.since.filter(|&since| since > 0);
+
+    let (pricebook, removed_items) = match (query.only, since) {
+        (Some(SyncKind::Accounts), _) => (Vec::new(), Vec::new()),
+        (_, Some(since)) => (
+            database::get_items_since(since)
+                .await
+                .map_err(|e| e.to_string())?,
+            database::get_removed_since("item", since)
+                .await
+                .map_err(|e| e.to_string())?,
+        ),
+        (_, None) => (
+            database::get_all_items().await.map_err(|e| e.to_string())?,
+            Vec::new(),
+        ),
+    };
+    let (accounts, removed_accounts, bunks) = match (query.only, since) {
+        (Some(SyncKind::Pricebook), _) => (Vec::new(), Vec::new(), Vec::new()),
+        (_, Some(since)) => (
+            database::get_accounts_since(since)
+                .await
+                .map_err(|e| e.to_string())?,
+            database::get_removed_since("account", since)
+                .await
+                .map_err(|e| e.to_string())?,
+            database::get_bunks().await.map_err(|e| e.to_string())?,
+        ),
+        (_, None) => (
+            database::get_all_accounts()
+                .await
+                .map_err(|e| e.to_string())?,
+            Vec::new(),
+            database::get_bunks().await.map_err(|e| e.to_string())?,
+        ),
+    };
 
     Ok(Json(SyncState {
+        seq,
+        pricebook_version,
         pricebook,
         accounts,
+        removed_items,
+        removed_accounts,
+        bunks,
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/events",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+    ),
+    responses(
+        (status = 200, description = "Server-sent stream of account and item changes", body = SyncEvent),
+        (status = 500, description = "Error subscribing to events", body = String),
+    ),
+)]
+pub async fn events(
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, String> {
+    info!("Events subscription request");
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    let subscription = (crate::events::subscribe(), crate::events::disconnected());
+    let stream = futures::stream::unfold(
+        subscription,
+        |(mut receiver, mut disconnected)| async move {
+            // event streams never finish on their own, so end them or shutdown would wait on them forever
+            let received = tokio::select! {
+                received = receiver.recv() => received,
+                _ = disconnected.changed() => return None,
+                _ = crate::shutdown::requested() => return None,
+            };
+
+            match received {
+                Ok(event) => Some((Event::default().json_data(event), (receiver, disconnected))),
+                // a register that fell behind has missed changes, so hang up and let it resync on reconnect
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(
+                        "Events subscriber lagged by {} events, closing stream",
+                        missed
+                    );
+                    None
+                }
+                Err(RecvError::Closed) => None,
+            }
+        },
+    );
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/accounts/balance/bunk",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+    ),
+    responses(
+        (status = 200, description = "Balance updated for every open account in the bunk", body = String),
+        (status = 500, description = "Error updating balances", body = String),
+    ),
+)]
+pub async fn update_bunk_balance(
+    headers: HeaderMap,
+    Json(payload): Json<BunkBalanceUpdate>,
+) -> Result<Json<String>, String> {
+    info!("Update bunk balance request: {:?}", payload);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    match database::update_bunk_balance(payload).await {
+        Ok(updated) => Ok(Json(format!("Balance updated for {} accounts", updated))),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/bunks",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+    ),
+    responses(
+        (status = 200, description = "Bunks", body = Vec<Bunk>),
+        (status = 500, description = "Error querying bunks", body = String),
+    ),
+)]
+pub async fn get_bunks(headers: HeaderMap) -> Result<Json<Vec<Bunk>>, String> {
+    info!("Get bunks request");
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    match database::get_bunks().await {
+        Ok(bunks) => Ok(Json(bunks)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/bunks/insert",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+    ),
+    responses(
+        (status = 200, description = "Bunk inserted", body = String),
+        (status = 500, description = "Error inserting bunk", body = String),
+    ),
+)]
+pub async fn insert_bunk(
+    headers: HeaderMap,
+    Json(payload): Json<Bunk>,
+) -> Result<Json<String>, String> {
+    info!("Insert bunk request: {:?}", payload);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    match database::insert_bunk(payload).await {
+        Ok(_) => Ok(Json("Bunk inserted".to_string())),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/items/insert",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+    ),
+    responses(
+        (status = 200, description = "Item inserted", body = String),
+        (status = 500, description = "Error inserting item", body = String),
+    ),
+)]
+pub async fn insert_item(
+    headers: HeaderMap,
+    Json(payload): Json<Item>,
+) -> Result<Json<String>, String> {
+    info!("Insert item request: {:?}", payload);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    match database::insert_item(payload).await {
+        Ok(_) => Ok(Json("Item inserted".to_string())),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/items/stock",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+    ),
+    responses(
+        (status = 200, description = "The item with its new quantity on hand", body = Item),
+        (status = 500, description = "Error updating stock", body = String),
+    ),
+)]
+pub async fn update_stock(
+    headers: HeaderMap,
+    Json(payload): Json<StockUpdate>,
+) -> Result<Json<Item>, String> {
+    info!("Update stock request: {:?}", payload);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    match database::update_stock(payload).await {
+        Ok(item) => Ok(Json(item)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct StockQuery {
+    /// First day to include, e.g. 2025-07-01
+    from: Option<NaiveDate>,
+    /// Last day to include
+    to: Option<NaiveDate>,
+    /// Only movements of this item
+    item_id: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/items/stock/movements",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        StockQuery,
+    ),
+    responses(
+        (status = 200, description = "Sales, deliveries, recounts and waste, oldest first", body = Vec<StockMovement>),
+        (status = 500, description = "Error getting stock movements", body = String),
+    ),
+)]
+pub async fn get_stock_movements(
+    headers: HeaderMap,
+    Query(query): Query<StockQuery>,
+) -> Result<Json<Vec<StockMovement>>, String> {
+    info!("Get stock movements request: {:?}", query);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    match database::get_stock_movements(query.from, query.to, query.item_id).await {
+        Ok(movements) => Ok(Json(movements)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/deliveries",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+    ),
+    responses(
+        (status = 200, description = "Every delivery with its lines, newest first", body = Vec<Delivery>),
+        (status = 500, description = "Error getting deliveries", body = String),
+    ),
+)]
+pub async fn get_deliveries(headers: HeaderMap) -> Result<Json<Vec<Delivery>>, String> {
+    info!("Get deliveries request");
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    match database::get_deliveries().await {
+        Ok(deliveries) => Ok(Json(deliveries)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CostQuery {
+    /// Only costs of this item
+    item_id: Option<u32>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/items/costs",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        CostQuery,
+    ),
+    responses(
+        (status = 200, description = "Unit cost on every received delivery line, oldest first", body = Vec<ItemCost>),
+        (status = 500, description = "Error getting costs", body = String),
+    ),
+)]
+pub async fn get_item_costs(
+    headers: HeaderMap,
+    Query(query): Query<CostQuery>,
+) -> Result<Json<Vec<ItemCost>>, String> {
+    info!("Get item costs request: {:?}", query);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    match database::get_item_costs(query.item_id).await {
+        Ok(costs) => Ok(Json(costs)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/categories",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+    ),
+    responses(
+        (status = 200, description = "Every item category in use", body = Vec<String>),
+        (status = 500, description = "Error getting categories", body = String),
+    ),
+)]
+pub async fn get_categories(headers: HeaderMap) -> Result<Json<Vec<String>>, String> {
+    info!("Get categories request");
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    match database::get_categories().await {
+        Ok(categories) => Ok(Json(categories)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/reports/bunks",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        DateRange,
+    ),
+    responses(
+        (status = 200, description = "Balances, deposits and spending per bunk", body = Vec<BunkSpending>),
+        (status = 500, description = "Error building report", body = String),
+    ),
+)]
+pub async fn bunk_report(
+    headers: HeaderMap,
+    Query(range): Query<DateRange>,
+) -> Result<Json<Vec<BunkSpending>>, String> {
+    info!("Bunk report request: {:?}", range);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    report::bunk_spending(&range)
+        .await
+        .map(Json)
+        .map_err(|e| e.to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/reports/margins",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        DateRange,
+    ),
+    responses(
+        (status = 200, description = "Revenue, cost of goods and margin per item and category", body = MarginReport),
+        (status = 500, description = "Error building report", body = String),
+    ),
+)]
+pub async fn margin_report(
+    headers: HeaderMap,
+    Query(range): Query<DateRange>,
+) -> Result<Json<MarginReport>, String> {
+    info!("Margin report request: {:?}", range);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    report::item_margins(&range)
+        .await
+        .map(Json)
+        .map_err(|e| e.to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/export/accounts.csv",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        ExportFilter,
+    ),
+    responses(
+        (status = 200, description = "Current balance of every account, as CSV", body = String, content_type = "text/csv"),
+        (status = 500, description = "Error exporting", body = String),
+    ),
+)]
+pub async fn export_accounts(
+    headers: HeaderMap,
+    Query(filter): Query<ExportFilter>,
+) -> Result<Response, String> {
+    info!("Export accounts request: {:?}", filter);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    let csv = export::accounts_csv(&filter)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(csv_response(
+        &format!("accounts{}.csv", filter.file_suffix()),
+        csv,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/export/history.csv",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        ExportFilter,
+    ),
+    responses(
+        (status = 200, description = "Completed transactions with item names, as CSV", body = String, content_type = "text/csv"),
+        (status = 500, description = "Error exporting", body = String),
+    ),
+)]
+pub async fn export_history(
+    headers: HeaderMap,
+    Query(filter): Query<ExportFilter>,
+) -> Result<Response, String> {
+    info!("Export history request: {:?}", filter);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    let csv = export::history_csv(&filter)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(csv_response(
+        &format!("history{}.csv", filter.file_suffix()),
+        csv,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/export/balance_changes.csv",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        ExportFilter,
+    ),
+    responses(
+        (status = 200, description = "Every change to account credit, as CSV", body = String, content_type = "text/csv"),
+        (status = 500, description = "Error exporting", body = String),
+    ),
+)]
+pub async fn export_balance_changes(
+    headers: HeaderMap,
+    Query(filter): Query<ExportFilter>,
+) -> Result<Response, String> {
+    info!("Export balance_changes request: {:?}", filter);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    let csv = export::balance_changes_csv(&filter)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(csv_response(
+        &format!("balance_changes{}.csv", filter.file_suffix()),
+        csv,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/export/bunks.csv",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        ExportFilter,
+    ),
+    responses(
+        (status = 200, description = "Balances, deposits and spending per bunk, as CSV", body = String, content_type = "text/csv"),
+        (status = 500, description = "Error exporting", body = String),
+    ),
+)]
+pub async fn export_bunks(
+    headers: HeaderMap,
+    Query(filter): Query<ExportFilter>,
+) -> Result<Response, String> {
+    info!("Export bunks request: {:?}", filter);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    let csv = export::bunks_csv(&filter)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(csv_response(
+        &format!("bunks{}.csv", filter.file_suffix()),
+        csv,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/export/margins.csv",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        ExportFilter,
+    ),
+    responses(
+        (status = 200, description = "Revenue, cost of goods and margin per item and category, as CSV", body = String, content_type = "text/csv"),
+        (status = 500, description = "Error exporting", body = String),
+    ),
+)]
+pub async fn export_margins(
+    headers: HeaderMap,
+    Query(filter): Query<ExportFilter>,
+) -> Result<Response, String> {
+    info!("Export margins request: {:?}", filter);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    let csv = export::margins_csv(&filter)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(csv_response(
+        &format!("margins{}.csv", filter.file_suffix()),
+        csv,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/statements/{account_id}",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        ("account_id" = u32, Path, description = "Account to print a statement for"),
+        DateRange,
+    ),
+    responses(
+        (status = 200, description = "Printable statement for the account", body = String, content_type = "text/html"),
+        (status = 500, description = "Error building statement", body = String),
+    ),
+)]
+pub async fn account_statement(
+    headers: HeaderMap,
+    Path(account_id): Path<u32>,
+    Query(range): Query<DateRange>,
+) -> Result<Html<String>, String> {
+    info!("Account statement request: {} {:?}", account_id, range);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    statement::account_statement(account_id, &range)
+        .await
+        .map(Html)
+        .map_err(|e| e.to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/statements/bunk/{bunk}",
+    params(
+        ("x-auth-token" = String, Header, description = "Authorization token"),
+        ("bunk" = u32, Path, description = "Bunk to print statements for"),
+        DateRange,
+    ),
+    responses(
+        (status = 200, description = "Printable statements for every account in the bunk, one per page", body = String, content_type = "text/html"),
+        (status = 500, description = "Error building statements", body = String),
+    ),
+)]
+pub async fn bunk_statements(
+    headers: HeaderMap,
+    Path(bunk): Path<u32>,
+    Query(range): Query<DateRange>,
+) -> Result<Html<String>, String> {
+    info!("Bunk statements request: {} {:?}", bunk, range);
+
+    if !check_auth(headers) {
+        return Err("Unauthorized".to_string());
+    }
+
+    statement::bunk_statements(bunk, &range)
+        .await
+        .map(Html)
+        .map_err(|e| e.to_string())
+}
+
+fn csv_response(file_name: &str, csv: String) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        csv,
+    )
+        .into_response()
+}
+
 pub fn check_auth(headers: HeaderMap) -> bool {
     headers
         .get("x-auth-token")
         .map(|val| val.to_str().unwrap_or_default() == *AUTH_KEY)
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use model::{
+        Account, BalanceChangeReason, BalanceUpdate, Item, StockReason, StockUpdate,
+        TransactionMethod, TransactionRequest, TransactionStatus, TxEntry, UpdateOperation,
+    };
+
+    use super::process_transaction;
+    use crate::database;
+
+    // every test gets its own item and account ids, they all share one database
+    async fn setup(id: u32, price: u32, credit: i32) {
+        database::init().await.unwrap();
+        database::insert_item(Item {
+            id,
+            name: format!("Test item {}", id),
+            gtin: None,
+            price,
+            category: None,
+            stock: None,
+            low_stock: None,
+            unit_cost: None,
+        })
+        .await
+        .unwrap();
+        database::insert_account(Account {
+            id,
+            name: format!("Test account {}", id),
+            credit,
+            overdraft: false,
+            discount: 0,
+            bunk: 0,
+            locked: false,
+            daily_limit: None,
+            weekly_limit: None,
+            transaction_limit: None,
+            blocked_items: Vec::new(),
+            blocked_categories: Vec::new(),
+        })
+        .await
+        .unwrap();
+    }
+
+    fn request(
+        tx_id: &str,
+        item: u32,
+        method: TransactionMethod,
+        remaining: Option<u32>,
+    ) -> TransactionRequest {
+        TransactionRequest {
+            tx_id: tx_id.to_string(),
+            items: vec![TxEntry {
+                id: item,
+                quantity: 1,
+            }],
+            method,
+            pricebook_version: None,
+            remaining,
+            offline_total: None,
+        }
+    }
+
+    async fn times_recorded(tx_id: &str) -> usize {
+        database::get_history(None, None, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.id == tx_id)
+            .count()
+    }
+
+    #[tokio::test]
+    async fn credit_retry_charges_once() {
+        setup(9101, 150, 1000).await;
+        let sale = request(
+            "retry-credit",
+            9101,
+            TransactionMethod::Credit { account_id: 9101 },
+            None,
+        );
+
+        let first = process_transaction(sale.clone()).await.unwrap();
+        let retry = process_transaction(sale).await.unwrap();
+
+        assert_eq!(first, TransactionStatus::Success { cash_back: 0 });
+        assert_eq!(retry, first);
+        assert_eq!(database::get_account(9101).await.unwrap().credit, 850);
+        assert_eq!(times_recorded("retry-credit").await, 1);
+    }
+
+    #[tokio::test]
+    async fn cash_retry_records_once() {
+        setup(9102, 150, 0).await;
+        let sale = request(
+            "retry-cash",
+            9102,
+            TransactionMethod::Cash { tender: 200 },
+            None,
+        );
+
+        let first = process_transaction(sale.clone()).await.unwrap();
+        let retry = process_transaction(sale).await.unwrap();
+
+        assert_eq!(first, TransactionStatus::Success { cash_back: 50 });
+        assert_eq!(retry, first);
+        assert_eq!(times_recorded("retry-cash").await, 1);
+    }
+
+    #[tokio::test]
+    async fn offline_sale_is_taken_at_the_register_total() {
+        setup(9104, 120, 0).await;
+        let version = database::get_pricebook_version().await.unwrap();
+        setup(9104, 150, 0).await;
+        let sale = TransactionRequest {
+            pricebook_version: Some(version),
+            offline_total: Some(120),
+            ..request(
+                "offline-cash",
+                9104,
+                TransactionMethod::Cash { tender: 200 },
+                None,
+            )
+        };
+
+        assert_eq!(
+            process_transaction(sale).await.unwrap(),
+            TransactionStatus::Success { cash_back: 80 }
+        );
+        assert_eq!(times_recorded("offline-cash").await, 1);
+    }
+
+    #[tokio::test]
+    async fn offline_total_has_to_match_the_register_prices() {
+        setup(9109, 150, 1000).await;
+        let version = database::get_pricebook_version().await.unwrap();
+        let sale = |tx_id: &str, pricebook_version: u64| TransactionRequest {
+            pricebook_version: Some(pricebook_version),
+            offline_total: Some(0),
+            ..request(
+                tx_id,
+                9109,
+                TransactionMethod::Credit { account_id: 9109 },
+                None,
+            )
+        };
+
+        for request in [
+            sale("offline-free", version),
+            sale("offline-future", u64::MAX),
+        ] {
+            assert!(matches!(
+                process_transaction(request).await.unwrap(),
+                TransactionStatus::PricesChanged { total: 150, .. }
+            ));
+        }
+        assert_eq!(times_recorded("offline-free").await, 0);
+        assert_eq!(database::get_account(9109).await.unwrap().credit, 1000);
+    }
+
+    #[tokio::test]
+    async fn partial_cash_retry_is_not_applied_twice() {
+        setup(9103, 150, 0).await;
+        let first_payment = request(
+            "retry-partial",
+            9103,
+            TransactionMethod::Cash { tender: 100 },
+            None,
+        );
+        let second_payment = request(
+            "retry-partial",
+            9103,
+            TransactionMethod::Cash { tender: 50 },
+            Some(50),
+        );
+
+        let first = process_transaction(first_payment.clone()).await.unwrap();
+        let retry = process_transaction(first_payment.clone()).await.unwrap();
+        assert_eq!(first, TransactionStatus::Partial { remaining: 50 });
+        assert_eq!(retry, first);
+        assert_eq!(times_recorded("retry-partial").await, 0);
+
+        let second = process_transaction(second_payment.clone()).await.unwrap();
+        let retry = process_transaction(second_payment).await.unwrap();
+        assert_eq!(second, TransactionStatus::Success { cash_back: 0 });
+        assert_eq!(retry, second);
+        // a very late retry of the first payment finds the sale finished
+        assert_eq!(process_transaction(first_payment).await.unwrap(), second);
+        assert_eq!(times_recorded("retry-partial").await, 1);
+    }
+
+    #[tokio::test]
+    async fn closed_account_balance_is_left_alone() {
+        setup(9105, 100, 500).await;
+        database::close_account(9105, BalanceChangeReason::CashRefund)
+            .await
+            .unwrap();
+
+        let deposit = BalanceUpdate {
+            id: 9105,
+            amount: 1000,
+            operation: UpdateOperation::Add,
+        };
+        assert!(matches!(
+            database::update_balance(deposit).await,
+            Err(database::DBError::AccountClosed(9105))
+        ));
+        assert_eq!(database::get_account(9105).await.unwrap().credit, 0);
+    }
+
+    #[tokio::test]
+    async fn concurrent_sales_cannot_both_fit_under_a_limit() {
+        setup(9106, 100, 1000).await;
+        let account = database::get_account(9106).await.unwrap();
+        database::insert_account(Account {
+            daily_limit: Some(150),
+            ..account
+        })
+        .await
+        .unwrap();
+
+        let credit = TransactionMethod::Credit { account_id: 9106 };
+        let (first, second) = tokio::join!(
+            process_transaction(request("limit-first", 9106, credit.clone(), None)),
+            process_transaction(request("limit-second", 9106, credit, None)),
+        );
+        let statuses = [first.unwrap(), second.unwrap()];
+        assert_eq!(
+            statuses
+                .iter()
+                .filter(|status| matches!(status, TransactionStatus::Success { .. }))
+                .count(),
+            1
+        );
+        assert!(statuses.iter().any(|status| matches!(
+            status,
+            TransactionStatus::LimitExceeded { remaining: 50, .. }
+        )));
+        assert_eq!(database::get_account(9106).await.unwrap().credit, 900);
+    }
+
+    #[tokio::test]
+    async fn cash_sale_records_what_was_charged() {
+        setup(9107, 120, 0).await;
+        let version = database::get_pricebook_version().await.unwrap();
+        setup(9107, 150, 0).await;
+        let first_payment = TransactionRequest {
+            pricebook_version: Some(version),
+            offline_total: Some(120),
+            ..request(
+                "charged-cash",
+                9107,
+                TransactionMethod::Cash { tender: 100 },
+                None,
+            )
+        };
+        let second_payment = request(
+            "charged-cash",
+            9107,
+            TransactionMethod::Cash { tender: 20 },
+            Some(20),
+        );
+
+        process_transaction(first_payment).await.unwrap();
+        process_transaction(second_payment).await.unwrap();
+
+        let entry = database::get_history(None, None, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.id == "charged-cash")
+            .unwrap();
+        assert_eq!(entry.total, Some(120));
+    }
+
+    #[tokio::test]
+    async fn tx_id_with_quotes_is_replayed() {
+        setup(9108, 150, 0).await;
+        let first_payment = request(
+            "o'brien'; --",
+            9108,
+            TransactionMethod::Cash { tender: 100 },
+            None,
+        );
+        let second_payment = request(
+            "o'brien'; --",
+            9108,
+            TransactionMethod::Cash { tender: 50 },
+            Some(50),
+        );
+
+        let partial = process_transaction(first_payment.clone()).await.unwrap();
+        assert_eq!(partial, TransactionStatus::Partial { remaining: 50 });
+        assert_eq!(process_transaction(first_payment).await.unwrap(), partial);
+
+        let done = process_transaction(second_payment.clone()).await.unwrap();
+        assert_eq!(done, TransactionStatus::Success { cash_back: 0 });
+        assert_eq!(process_transaction(second_payment).await.unwrap(), done);
+        assert_eq!(times_recorded("o'brien'; --").await, 1);
+    }
+
+    #[tokio::test]
+    async fn saving_a_closed_account_keeps_it_closed() {
+        setup(9114, 100, 500).await;
+        database::close_account(9114, BalanceChangeReason::Donation)
+            .await
+            .unwrap();
+        let account = database::get_account(9114).await.unwrap();
+
+        assert!(matches!(
+            database::insert_account(Account {
+                credit: 800,
+                ..account.clone()
+            })
+            .await,
+            Err(database::DBError::AccountClosed(9114))
+        ));
+        database::insert_account(Account {
+            name: "Renamed".to_string(),
+            locked: false,
+            ..account
+        })
+        .await
+        .unwrap();
+        let account = database::get_account(9114).await.unwrap();
+        assert!(account.locked);
+        assert_eq!(account.credit, 0);
+
+        database::reopen_account(9114).await.unwrap();
+        assert!(!database::get_account(9114).await.unwrap().locked);
+    }
+
+    #[tokio::test]
+    async fn concurrent_sales_cannot_both_spend_the_same_credit() {
+        setup(9116, 300, 500).await;
+
+        let credit = TransactionMethod::Credit { account_id: 9116 };
+        let (first, second) = tokio::join!(
+            process_transaction(request("credit-first", 9116, credit.clone(), None)),
+            process_transaction(request("credit-second", 9116, credit, None)),
+        );
+        let statuses = [first.unwrap(), second.unwrap()];
+        assert!(statuses.contains(&TransactionStatus::Success { cash_back: 0 }));
+        assert!(statuses.contains(&TransactionStatus::Failure {
+            reason: "Insufficient Credit.".to_string()
+        }));
+        assert_eq!(database::get_account(9116).await.unwrap().credit, 200);
+    }
+
+    #[tokio::test]
+    async fn saving_a_stale_account_leaves_its_balance_alone() {
+        setup(9117, 100, 1000).await;
+        let loaded = database::get_account(9117).await.unwrap();
+        let sale = request(
+            "stale-sale",
+            9117,
+            TransactionMethod::Credit { account_id: 9117 },
+            None,
+        );
+        process_transaction(sale).await.unwrap();
+
+        // only the name was edited, the sale stays charged
+        database::save_account(
+            Account {
+                name: "Renamed".to_string(),
+                ..loaded.clone()
+            },
+            loaded.credit,
+        )
+        .await
+        .unwrap();
+        let saved = database::get_account(9117).await.unwrap();
+        assert_eq!(saved.name, "Renamed");
+        assert_eq!(saved.credit, 900);
+
+        // an edited balance from before the sale would undo it
+        assert!(matches!(
+            database::save_account(
+                Account {
+                    credit: 2000,
+                    ..loaded.clone()
+                },
+                loaded.credit
+            )
+            .await,
+            Err(database::DBError::BalanceChanged(9117))
+        ));
+        assert_eq!(database::get_account(9117).await.unwrap().credit, 900);
+    }
+
+    #[tokio::test]
+    async fn receive_and_waste_need_a_positive_quantity() {
+        setup(9118, 100, 0).await;
+        for reason in [StockReason::Receive, StockReason::Waste] {
+            for quantity in [0, -5] {
+                assert!(matches!(
+                    database::update_stock(StockUpdate {
+                        item_id: 9118,
+                        quantity,
+                        reason,
+                        note: None,
+                    })
+                    .await,
+                    Err(database::DBError::NonPositiveQuantity(_))
+                ));
+            }
+        }
+        let item = database::get_items(vec![9118])
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(item.stock, None);
+    }
+
+    #[tokio::test]
+    async fn saving_an_item_keeps_its_received_cost() {
+        setup(9119, 100, 0).await;
+        let item = database::get_items(vec![9119])
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        database::generic_exec("UPDATE Pricebook SET unit_cost = 60 WHERE id = 9119")
+            .await
+            .unwrap();
+
+        // the editor still has the item as it was before the delivery came in
+        database::insert_item(Item {
+            low_stock: Some(3),
+            ..item
+        })
+        .await
+        .unwrap();
+        let saved = database::get_items(vec![9119])
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(saved.low_stock, Some(3));
+        assert_eq!(saved.unit_cost, Some(60));
+    }
+}
@@ -0,0 +1,44 @@
+use std::{
+    sync::{Condvar, LazyLock, Mutex},
+    time::Duration,
+};
+
+use log::warn;
+use tokio::sync::watch;
+
+static REQUESTED: LazyLock<watch::Sender<bool>> = LazyLock::new(|| watch::channel(false).0);
+static FINISHED: LazyLock<(Mutex<bool>, Condvar)> =
+    LazyLock::new(|| (Mutex::new(false), Condvar::new()));
+
+/// Asks the server to stop accepting requests, drain the ones in flight and close the database.
+pub fn request() {
+    REQUESTED.send_replace(true);
+}
+
+pub async fn requested() {
+    let mut receiver = REQUESTED.subscribe();
+    let _ = receiver.wait_for(|&requested| requested).await;
+}
+
+pub fn mark_finished() {
+    let (finished, condvar) = &*FINISHED;
+    if let Ok(mut finished) = finished.lock() {
+        *finished = true;
+        condvar.notify_all();
+    }
+}
+
+/// Blocks the calling (non-async) thread until the server has finished shutting down, or gives up after `timeout`.
+pub fn wait_finished(timeout: Duration) {
+    let (finished, condvar) = &*FINISHED;
+    let Ok(finished) = finished.lock() else {
+        return;
+    };
+
+    match condvar.wait_timeout_while(finished, timeout, |finished| !*finished) {
+        Ok((_, result)) if result.timed_out() => {
+            warn!("Server didn't finish shutting down within {:?}", timeout)
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use chrono::Local;
+use model::{Account, BalanceChange, BalanceChangeReason, HistoryEntry};
+use thiserror::Error;
+
+use crate::{
+    database::{self, DBError},
+    export::{DateRange, dollars},
+};
+
+#[derive(Debug, Error)]
+pub enum StatementError {
+    #[error("{0}")]
+    Database(#[from] DBError),
+    #[error("No accounts in bunk {0}")]
+    EmptyBunk(u32),
+}
+
+fn describe(range: &DateRange) -> String {
+    match (range.from, range.to) {
+        (None, None) => "Whole session".to_string(),
+        (Some(from), None) => format!("From {}", from),
+        (None, Some(to)) => format!("Up to {}", to),
+        (Some(from), Some(to)) => format!("{} to {}", from, to),
+    }
+}
+
+const STYLE: &str = "
+body { font-family: sans-serif; font-size: 11pt; margin: 2em; }
+h1 { font-size: 16pt; margin-bottom: 0; }
+.details { color: #555; margin-bottom: 1em; }
+table { width: 100%; border-collapse: collapse; }
+th, td { text-align: left; padding: 4px 6px; border-bottom: 1px solid #ddd; vertical-align: top; }
+th.amount, td.amount { text-align: right; white-space: nowrap; }
+tr.item td { border-bottom: none; color: #555; padding-top: 0; }
+tr.item td:nth-child(2) { padding-left: 2em; }
+tr.total td { font-weight: bold; border-top: 2px solid #000; }
+section { page-break-after: always; }
+section:last-of-type { page-break-after: auto; }
+@media print { body { margin: 0; } }
+";
+
+/// A printable statement for one account, as a complete HTML document.
+pub async fn account_statement(
+    account_id: u32,
+    range: &DateRange,
+) -> Result<String, StatementError> {
+    let account = database::get_account(account_id).await?;
+    let section = statement_section(&account, range, &item_names().await?).await?;
+    Ok(document(&format!("Statement - {}", account.name), &section))
+}
+
+/// Statements for every account in a bunk, one per printed page, sorted by name.
+pub async fn bunk_statements(bunk: u32, range: &DateRange) -> Result<String, StatementError> {
+    let mut accounts = database::get_all_accounts().await?;
+    accounts.retain(|account| account.bunk == bunk);
+    if accounts.is_empty() {
+        return Err(StatementError::EmptyBunk(bunk));
+    }
+    accounts.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let item_names = item_names().await?;
+    let mut sections = String::new();
+    for account in &accounts {
+        sections.push_str(&statement_section(account, range, &item_names).await?);
+    }
+    Ok(document(&format!("Statements - Bunk {}", bunk), &sections))
+}
+
+async fn item_names() -> Result<HashMap<u32, String>, StatementError> {
+    Ok(database::get_all_items()
+        .await?
+        .into_iter()
+        .map(|item| (item.id, item.name))
+        .collect())
+}
+
+fn document(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+async fn statement_section(
+    account: &Account,
+    range: &DateRange,
+    item_names: &HashMap<u32, String>,
+) -> Result<String, StatementError> {
+    let changes = database::get_balance_changes(range.from, range.to, Some(account.id)).await?;
+    let history = database::get_history(range.from, range.to, Some(account.id))
+        .await?
+        .into_iter()
+        .map(|entry| (entry.id.clone(), entry))
+        .collect::<HashMap<_, _>>();
+
+    // a range with nothing in it still has a balance, which is whatever it was before and after
+    let (opening, closing) = match (changes.first(), changes.last()) {
+        (Some(first), Some(last)) => (
+            first.balance as i64 - first.amount as i64,
+            last.balance as i64,
+        ),
+        _ => {
+            let later = database::get_balance_changes(
+                range.to.and_then(|to| to.succ_opt()),
+                None,
+                Some(account.id),
+            )
+            .await?;
+            let balance = match (range.to, later.first()) {
+                (Some(_), Some(next)) => next.balance as i64 - next.amount as i64,
+                _ => account.credit as i64,
+            };
+            (balance, balance)
+        }
+    };
+
+    let mut rows = format!(
+        "<tr><td></td><td>Opening balance</td><td class=\"amount\"></td><td class=\"amount\">{}</td></tr>\n",
+        money(opening)
+    );
+    for change in &changes {
+        rows.push_str(&change_rows(change, &history, item_names));
+    }
+    rows.push_str(&format!(
+        "<tr class=\"total\"><td></td><td>Closing balance</td><td class=\"amount\"></td><td class=\"amount\">{}</td></tr>\n",
+        money(closing)
+    ));
+
+    Ok(format!(
+        "<section>\n<h1>{}</h1>\n<div class=\"details\">Account #{:04} &middot; Bunk {} &middot; {} &middot; Generated {}</div>\n<table>\n<thead><tr><th>Date</th><th>Description</th><th class=\"amount\">Amount</th><th class=\"amount\">Balance</th></tr></thead>\n<tbody>\n{}</tbody>\n</table>\n</section>\n",
+        escape(&account.name),
+        account.id,
+        account.bunk,
+        escape(&describe(range)),
+        Local::now().format("%Y-%m-%d %H:%M"),
+        rows
+    ))
+}
+
+fn change_rows(
+    change: &BalanceChange,
+    history: &HashMap<String, HistoryEntry>,
+    item_names: &HashMap<u32, String>,
+) -> String {
+    let description = match change.reason {
+        BalanceChangeReason::Purchase => "Purchase",
+        BalanceChangeReason::Deposit => "Deposit",
+        BalanceChangeReason::Withdrawal => "Withdrawal",
+        BalanceChangeReason::Adjustment => "Adjustment",
+        BalanceChangeReason::Import => "Opening deposit",
+        BalanceChangeReason::CashRefund => "Refunded in cash",
+        BalanceChangeReason::CheckRefund => "Refunded by check",
+        BalanceChangeReason::Donation => "Donated",
+    };
+    let mut rows = format!(
+        "<tr><td>{}</td><td>{}</td><td class=\"amount\">{}</td><td class=\"amount\">{}</td></tr>\n",
+        escape(&change.created_at),
+        description,
+        money(change.amount as i64),
+        money(change.balance as i64)
+    );
+
+    let Some(entry) = change.tx_id.as_ref().and_then(|tx_id| history.get(tx_id)) else {
+        return rows;
+    };
+
+    let mut items_total = 0;
+    for item in &entry.items {
+        let name = item_names
+            .get(&item.id)
+            .cloned()
+            .unwrap_or_else(|| format!("Item #{:04}", item.id));
+        let line = match entry.prices.get(&item.id) {
+            Some(&price) => {
+                items_total += (price * item.quantity) as i64;
+                format!(
+                    "{} x {} @ {}",
+                    item.quantity,
+                    escape(&name),
+                    money(price as i64)
+                )
+            }
+            // sold before prices were kept with the sale
+            None => format!("{} x {}", item.quantity, escape(&name)),
+        };
+        rows.push_str(&format!(
+            "<tr class=\"item\"><td></td><td>{}</td><td class=\"amount\"></td><td class=\"amount\"></td></tr>\n",
+            line
+        ));
+    }
+
+    // only every item having a price says what the sale should have come to
+    let discount = entry
+        .total
+        .filter(|_| entry.prices.len() == entry.items.len())
+        .map(|total| total as i64 - items_total)
+        .filter(|&discount| discount != 0);
+    if let Some(discount) = discount {
+        rows.push_str(&format!(
+            "<tr class=\"item\"><td></td><td>Discount</td><td class=\"amount\">{}</td><td class=\"amount\"></td></tr>\n",
+            money(discount)
+        ));
+    }
+    rows
+}
+
+fn money(cents: i64) -> String {
+    match dollars(cents).strip_prefix('-') {
+        Some(amount) => format!("-${}", amount),
+        None => format!("${}", dollars(cents)),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
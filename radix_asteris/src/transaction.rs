@@ -1,8 +1,132 @@
 use std::collections::HashMap;
 
-use crate::database;
+use crate::database::{self, DBError};
+use itertools::Itertools;
 use log::{error, info};
-use model::TransactionStatus;
+use model::{Account, TransactionRejection, TransactionStatus, TxEntry};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TransactionError {
+    #[error("Transaction rejected: {0}")]
+    Rejected(TransactionRejection),
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl From<String> for TransactionError {
+    fn from(reason: String) -> Self {
+        Self::Internal(reason)
+    }
+}
+
+// balances and change are worked out as i32, so no sale may come to more than that
+const MAX_TOTAL: u32 = i32::MAX as u32;
+
+fn line_total(price: u32, quantity: u32) -> Option<u32> {
+    price
+        .checked_mul(quantity)
+        .filter(|&total| total <= MAX_TOTAL)
+}
+
+/// Rejects anything a stale register could otherwise sell for free, and collapses the rest into id -> quantity.
+pub async fn validate_items(items: Vec<TxEntry>) -> Result<HashMap<u32, u32>, TransactionError> {
+    if items.is_empty() {
+        return Err(TransactionError::Rejected(
+            TransactionRejection::EmptyTransaction,
+        ));
+    }
+
+    let prices = database::get_prices(items.iter().map(|item| item.id).collect())
+        .await
+        .map_err(|e| format!("Error looking up prices: {}", e))?;
+
+    let unknown_items: Vec<u32> = items
+        .iter()
+        .map(|item| item.id)
+        .filter(|id| !prices.contains_key(id))
+        .sorted()
+        .dedup()
+        .collect();
+    let zero_quantity: Vec<u32> = items
+        .iter()
+        .filter(|item| item.quantity == 0)
+        .map(|item| item.id)
+        .sorted()
+        .dedup()
+        .collect();
+
+    if !unknown_items.is_empty() || !zero_quantity.is_empty() {
+        return Err(TransactionError::Rejected(
+            TransactionRejection::InvalidItems {
+                unknown_items,
+                zero_quantity,
+            },
+        ));
+    }
+
+    // the same item can be rung up on several lines, and nothing stops a register sending absurd quantities
+    let mut quantities: HashMap<u32, u32> = HashMap::new();
+    let mut too_large = Vec::new();
+    for TxEntry { id, quantity } in items {
+        let total = quantities.entry(id).or_insert(0);
+        match total.checked_add(quantity) {
+            Some(sum) => *total = sum,
+            None => too_large.push(id),
+        }
+    }
+    too_large.extend(
+        quantities
+            .iter()
+            .filter(|(id, quantity)| line_total(prices[id], **quantity).is_none())
+            .map(|(&id, _)| id),
+    );
+    // every line fits but the sale as a whole doesn't
+    if too_large.is_empty()
+        && quantities
+            .iter()
+            .try_fold(0u32, |sum, (id, quantity)| {
+                sum.checked_add(prices[id] * quantity)
+                    .filter(|&sum| sum <= MAX_TOTAL)
+            })
+            .is_none()
+    {
+        too_large.extend(quantities.keys().copied());
+    }
+    if !too_large.is_empty() {
+        return Err(TransactionError::Rejected(
+            TransactionRejection::QuantityTooLarge {
+                items: too_large.into_iter().sorted().dedup().collect(),
+            },
+        ));
+    }
+
+    Ok(quantities)
+}
+
+/// Completed transactions are keyed by `tx_id`, so a register retrying after a timeout gets the
+/// original outcome back instead of being charged again.
+pub async fn replay_transaction(tx_id: String) -> Result<Option<TransactionStatus>, String> {
+    database::get_transaction(tx_id)
+        .await
+        .map(|transaction| {
+            transaction.map(|transaction| TransactionStatus::Success {
+                cash_back: transaction.cash_back,
+            })
+        })
+        .map_err(|e| format!("Error looking up completed transaction: {}", e))
+}
+
+/// A partly paid cash sale keeps what's still owed on record. A request that wasn't paying against that
+/// amount is a retry of a payment already taken, so it gets the same `Partial` back rather than being applied again.
+pub async fn replay_partial(tx_id: String, remaining: Option<u32>) -> Option<TransactionStatus> {
+    database::check_partial_transaction(tx_id)
+        .await
+        .filter(|&owed| remaining != Some(owed))
+        .map(|owed| TransactionStatus::Partial { remaining: owed })
+}
 
 pub async fn calc_total(tx_id: String, items: HashMap<u32, u32>) -> Result<u32, String> {
     match database::check_partial_transaction(tx_id.clone()).await {
@@ -32,8 +156,13 @@ pub async fn handle_cash(
 ) -> Result<TransactionStatus, String> {
     let difference: i32 = total as i32 - cash_amount as i32;
     if difference > 0 {
-        match database::create_partial_transaction(tx_id.clone(), items.clone(), difference as u32)
-            .await
+        match database::create_partial_transaction(
+            tx_id.clone(),
+            items.clone(),
+            difference as u32,
+            total,
+        )
+        .await
         {
             Ok(_) => Ok(TransactionStatus::Partial {
                 remaining: difference as u32,
@@ -46,7 +175,7 @@ pub async fn handle_cash(
     } else {
         // if it's zero or cashback
         if let Err(e) =
-            database::log_transaction(tx_id.clone(), items, difference.unsigned_abs()).await
+            database::log_transaction(tx_id.clone(), items, difference.unsigned_abs(), total).await
         {
             error!(
                 "Non-critical logging error, still sending completion: {}",
@@ -72,23 +201,23 @@ pub async fn handle_credit(
     total: u32,
 ) -> Result<TransactionStatus, String> {
     match database::get_account(account_id).await {
+        Ok(account) if account.locked => Ok(TransactionStatus::AccountClosed { account_id }),
         Ok(account) => {
-            //                     e.g. 9000  - (9000  * (       10        / 100)) = 9000 - 900 = 8100
-            let discounted_total = total - (total * (account.discount / 100));
-
-            // screen for insufficient credit
-            if account.credit < discounted_total as i32 && !account.overdraft {
-                return Ok(TransactionStatus::Failure {
-                    reason: "Insufficient Credit.".to_string(),
-                });
+            if let Some(status) = check_blocked(&account, &items).await? {
+                return Ok(status);
             }
 
-            match database::deduct_balance(account_id, discounted_total).await {
-                Ok(_) => {
-                    if let Err(e) = database::log_transaction(tx_id.clone(), items, 0).await {
-                        error!("Non-critical logging error, continuing: {}", e);
-                    }
+            let discounted_total = account.discounted(total);
 
+            match database::complete_credit_transaction(
+                tx_id.clone(),
+                &account,
+                items,
+                discounted_total,
+            )
+            .await
+            {
+                Ok(_) => {
                     if let Err(e) = database::drop_partial_transaction(tx_id).await {
                         error!(
                             "Error dropping partial tx that may or may not exist, continuing regardless: {}",
@@ -98,6 +227,29 @@ pub async fn handle_credit(
 
                     Ok(TransactionStatus::Success { cash_back: 0 })
                 }
+                // a retry raced the original request and lost, hand back what the original got
+                Err(DBError::DuplicateTransaction(tx_id)) => replay_transaction(tx_id.clone())
+                    .await?
+                    .ok_or(format!("Transaction {} vanished while replaying", tx_id)),
+                Err(DBError::AccountClosed(account_id)) => {
+                    Ok(TransactionStatus::AccountClosed { account_id })
+                }
+                Err(DBError::InsufficientCredit(_)) => Ok(TransactionStatus::Failure {
+                    reason: "Insufficient Credit.".to_string(),
+                }),
+                Err(DBError::LimitExceeded {
+                    limit,
+                    cap,
+                    remaining,
+                    remaining_today,
+                    ..
+                }) => Ok(TransactionStatus::LimitExceeded {
+                    limit,
+                    cap,
+                    remaining,
+                    remaining_today,
+                }),
+                Err(DBError::OutOfStock(items)) => Ok(TransactionStatus::OutOfStock { items }),
                 Err(e) => Err(format!(
                     "Error deducting balance from account with id {}: {}",
                     account_id, e
@@ -107,3 +259,162 @@ pub async fn handle_credit(
         Err(e) => Err(format!("Error looking up account {}: {}", account_id, e)),
     }
 }
+
+/// Counted items in the sale without enough on hand, as the status to send back. Only a first look,
+/// credit sales check again as they're recorded, but cash sales need it before any money changes hands.
+pub async fn check_stock(items: &HashMap<u32, u32>) -> Result<Option<TransactionStatus>, String> {
+    let short = database::get_items(items.keys().copied().collect())
+        .await
+        .map_err(|e| format!("Error looking up stock: {}", e))?
+        .into_iter()
+        .filter(|item| {
+            item.stock.is_some_and(|stock| {
+                stock < items.get(&item.id).copied().unwrap_or_default() as i32
+            })
+        })
+        .map(|item| item.id)
+        .sorted()
+        .collect::<Vec<_>>();
+    if short.is_empty() {
+        return Ok(None);
+    }
+    info!("Not enough on hand of items {:?}", short);
+    Ok(Some(TransactionStatus::OutOfStock { items: short }))
+}
+
+/// The items in the sale the account isn't allowed to buy, as the status to send back.
+async fn check_blocked(
+    account: &Account,
+    items: &HashMap<u32, u32>,
+) -> Result<Option<TransactionStatus>, String> {
+    if account.blocked_items.is_empty() && account.blocked_categories.is_empty() {
+        return Ok(None);
+    }
+
+    let blocked = database::get_items(items.keys().copied().collect())
+        .await
+        .map_err(|e| format!("Error looking up items for account {}: {}", account.id, e))?
+        .into_iter()
+        .filter(|item| account.blocks(item))
+        .map(|item| item.id)
+        .sorted()
+        .collect::<Vec<_>>();
+    if blocked.is_empty() {
+        return Ok(None);
+    }
+    info!("Account {} can't buy items {:?}", account.id, blocked);
+    Ok(Some(TransactionStatus::ItemsBlocked { items: blocked }))
+}
+
+#[cfg(test)]
+mod tests {
+    use model::Item;
+
+    use super::*;
+
+    async fn item(id: u32, price: u32) {
+        database::init().await.unwrap();
+        database::insert_item(Item {
+            id,
+            name: format!("Test item {}", id),
+            gtin: None,
+            price,
+            category: None,
+            stock: None,
+            low_stock: None,
+            unit_cost: None,
+        })
+        .await
+        .unwrap();
+    }
+
+    async fn rejection(items: Vec<TxEntry>) -> TransactionRejection {
+        match validate_items(items).await {
+            Err(TransactionError::Rejected(rejection)) => rejection,
+            other => panic!(
+                "expected a rejection, got {:?}",
+                other.map_err(|e| e.to_string())
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_sale_is_rejected() {
+        assert_eq!(
+            rejection(vec![]).await,
+            TransactionRejection::EmptyTransaction
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_and_zero_quantity_items_are_rejected() {
+        item(9110, 100).await;
+        assert_eq!(
+            rejection(vec![
+                TxEntry {
+                    id: 9110,
+                    quantity: 0
+                },
+                TxEntry {
+                    id: 9199,
+                    quantity: 1
+                },
+            ])
+            .await,
+            TransactionRejection::InvalidItems {
+                unknown_items: vec![9199],
+                zero_quantity: vec![9110],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn lines_are_merged() {
+        item(9111, 100).await;
+        let line = TxEntry {
+            id: 9111,
+            quantity: 2,
+        };
+        let quantities = validate_items(vec![line.clone(), line]).await.unwrap();
+        assert_eq!(quantities, HashMap::from([(9111, 4)]));
+    }
+
+    #[tokio::test]
+    async fn totals_past_i32_are_rejected() {
+        item(9112, 1000).await;
+        item(9113, 2).await;
+        let too_large = TransactionRejection::QuantityTooLarge { items: vec![9112] };
+
+        // fits in a u32 but would come out negative as a balance
+        let line = TxEntry {
+            id: 9112,
+            quantity: 3_000_000,
+        };
+        assert_eq!(rejection(vec![line]).await, too_large);
+
+        // the quantities themselves overflow when merged
+        let line = TxEntry {
+            id: 9112,
+            quantity: u32::MAX,
+        };
+        assert_eq!(rejection(vec![line.clone(), line]).await, too_large);
+
+        // each line fits, the sale doesn't
+        let lines = vec![
+            TxEntry {
+                id: 9112,
+                quantity: 2_000_000,
+            },
+            TxEntry {
+                id: 9113,
+                quantity: 500_000_000,
+            },
+        ];
+        assert_eq!(
+            rejection(lines).await,
+            TransactionRejection::QuantityTooLarge {
+                items: vec![9112, 9113]
+            }
+        );
+    }
+}
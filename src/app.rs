@@ -4,10 +4,11 @@ use dioxus::prelude::*;
 use model::{Account, Item, SyncState};
 
 use crate::{
-    components::sidebar::Sidebar,
+    components::{discovery::ServerPicker, offline::OfflineBanner, sidebar::Sidebar},
     forms::{
         balance::Balance, register::Register, Form,
     },
+    offline::{self, OFFLINE, OFFLINE_QUEUE},
 };
 
 #[component]
@@ -21,11 +22,14 @@ pub fn App() -> Element {
         spawn(async move {
             loop {
                 tracing::info!("Querying backend for pricebook...");
-                if let Ok(response) = crate::CLIENT.get("http://localhost:5555/sync").send().await {
+                if let Ok(response) = crate::CLIENT.get(crate::config::api_url("/sync")).send().await {
                     let sync_state = response
                         .json::<SyncState>()
                         .await
                         .expect("Got malformed state contents from Radix");
+                    offline::cache_sync_state(&sync_state);
+                    *crate::PRICEBOOK_SEQ.write() = sync_state.seq;
+                    *crate::ACCOUNTS_SEQ.write() = sync_state.seq;
                     let pb = sync_state.pricebook.into_iter()
                         .map(|i| (i.id, i))
                         .collect::<HashMap<u32, Item>>();
@@ -35,6 +39,24 @@ pub fn App() -> Element {
 
                     pricebook.set(pb);
                     accounts.set(ac);
+                    *crate::BUNKS.write() = sync_state.bunks.into_iter().map(|b| (b.id, b)).collect();
+                    *crate::PRICEBOOK_VERSION.write() = sync_state.pricebook_version;
+
+                    // sales left over from a previous offline session have to go out before anything new
+                    if !OFFLINE_QUEUE().pending.is_empty() {
+                        *OFFLINE.write() = true;
+                    }
+
+                    loaded.set(true);
+
+                    break;
+                } else if let Some(sync_state) = offline::load_sync_cache() {
+                    tracing::warn!("Backend unreachable, starting in offline mode from cached state");
+                    pricebook.set(sync_state.pricebook.into_iter().map(|i| (i.id, i)).collect());
+                    accounts.set(sync_state.accounts.into_iter().map(|a| (a.id, a)).collect());
+                    *crate::BUNKS.write() = sync_state.bunks.into_iter().map(|b| (b.id, b)).collect();
+                    *crate::PRICEBOOK_VERSION.write() = sync_state.pricebook_version;
+                    *OFFLINE.write() = true;
 
                     loaded.set(true);
 
@@ -46,6 +68,18 @@ pub fn App() -> Element {
         });
     }
 
+    use_future(move || crate::events::subscribe_events(pricebook, accounts));
+    use_future(crate::health::poll_health);
+
+    use_future(move || async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            if loaded() && OFFLINE() {
+                offline::try_reconnect(pricebook, accounts).await;
+            }
+        }
+    });
+
     let navigator = use_signal(|| Form::Register);
 
     rsx! {
@@ -57,17 +91,19 @@ pub fn App() -> Element {
                     Form::Register => rsx! { Register { pricebook, accounts } },
                     Form::Balance => rsx! { Balance { accounts } },
                 }}
+                OfflineBanner {}
             }
         } else {
             rsx! {
                 div {
                     class: "absolute top-0 left-0 flex justify-center items-center w-screen h-screen",
                     div {
-                        class: "card w-80 h-28 bg-base-100 shadow-sm",
+                        class: "card w-108 bg-base-100 shadow-sm",
                         div {
                             class: "card-body text-lg text-center",
                             "Backend not running"
                         }
+                        ServerPicker {}
                         div {
                             class: "italic text-[12px] text-gray-400 text-center p-2",
                             "If the problem persists, please contact a supervisor."
@@ -1,3 +1,6 @@
 pub mod searchbox;
 pub mod sidebar;
+pub mod discovery;
 pub mod layout;
+pub mod offline;
+pub mod bunkfilter;
@@ -0,0 +1,28 @@
+use std::collections::{BTreeMap, HashMap};
+
+use dioxus::prelude::*;
+use model::Account;
+
+/// Picks a bunk to narrow an account list down to, `None` being every bunk.
+#[component]
+pub fn BunkFilter(selected: Signal<Option<u32>>, accounts: Signal<HashMap<u32, Account>>) -> Element {
+    // an account can point at a bunk the register hasn't heard about yet, it still gets an entry
+    let mut bunks = accounts().values()
+        .map(|account| (account.bunk, if account.bunk == 0 { "No bunk".to_string() } else { format!("Bunk {}", account.bunk) }))
+        .collect::<BTreeMap<u32, String>>();
+    for bunk in crate::BUNKS().into_values() {
+        let label = if bunk.counselor.is_empty() { format!("{} - {}", bunk.id, bunk.name) } else { format!("{} - {} ({})", bunk.id, bunk.name, bunk.counselor) };
+        bunks.insert(bunk.id, label);
+    }
+
+    rsx! {
+        select {
+            class: "select w-64",
+            onchange: move |e| selected.set(e.value().parse().ok()),
+            option { value: "", selected: selected().is_none(), "All bunks" }
+            {bunks.into_iter().map(|(id, label)| rsx! {
+                option { key: "{id}", value: "{id}", selected: selected() == Some(id), {label} }
+            })}
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use dioxus::prelude::*;
+use model::{ServerBeacon, DISCOVERY_PORT};
+use tokio::net::UdpSocket;
+
+use crate::config::{server_url, set_server_url};
+
+// servers announce every couple of seconds, so one we haven't heard from in a while has gone away
+const BEACON_EXPIRY: Duration = Duration::from_secs(10);
+
+#[component]
+pub fn ServerPicker() -> Element {
+    let mut servers: Signal<BTreeMap<String, (ServerBeacon, tokio::time::Instant)>> = use_signal(BTreeMap::new);
+    let mut current_url = use_signal(server_url);
+
+    use_future(move || async move {
+        let socket = match UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT)).await {
+            Ok(socket) => socket,
+            Err(e) => {
+                tracing::error!("Unable to listen for server beacons: {:?}", e);
+                return;
+            }
+        };
+
+        let mut buffer = [0u8; 1024];
+        loop {
+            let Ok(Ok((len, addr))) = tokio::time::timeout(BEACON_EXPIRY, socket.recv_from(&mut buffer)).await else {
+                servers.write().retain(|_, (_, seen)| seen.elapsed() < BEACON_EXPIRY);
+                continue;
+            };
+
+            match serde_json::from_slice::<ServerBeacon>(&buffer[..len]) {
+                Ok(beacon) => {
+                    let url = format!("http://{}:{}", addr.ip(), beacon.port);
+                    let mut servers = servers.write();
+                    servers.insert(url, (beacon, tokio::time::Instant::now()));
+                    servers.retain(|_, (_, seen)| seen.elapsed() < BEACON_EXPIRY);
+                },
+                Err(e) => tracing::debug!("Ignoring malformed beacon from {}: {:?}", addr, e),
+            }
+        }
+    });
+
+    let server_elements = servers().into_iter().map(|(url, (beacon, _))| {
+        let selected = url == current_url();
+        rsx! {
+            tr {
+                key: "{url}",
+                class: format!("hover:bg-base-300 {}", if selected { "bg-base-300" } else { "" }),
+                onclick: move |_| {
+                    set_server_url(url.clone());
+                    current_url.set(url.clone());
+                },
+                td { "{beacon.name}" }
+                td { "{url}" }
+                td { "{beacon.version}" }
+            }
+        }
+    });
+
+    rsx! {
+        div {
+            class: "flex flex-col gap-1 p-2",
+            div { class: "text-sm text-center", {format!("Looking for {}", current_url())} }
+            {if servers().is_empty() {
+                rsx! { div { class: "italic text-[12px] text-gray-400 text-center", "No servers found on the network yet." } }
+            } else {
+                rsx! {
+                    table {
+                        class: "table table-sm",
+                        tbody { {server_elements} }
+                    }
+                }
+            }}
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use dioxus::prelude::*;
+
+use crate::{
+    offline::{clear_conflicts, OFFLINE, OFFLINE_QUEUE},
+    util::amount_pretty,
+};
+
+#[component]
+pub fn OfflineBanner() -> Element {
+    let mut show_conflicts = use_signal(|| false);
+
+    let queue = OFFLINE_QUEUE();
+    if !OFFLINE() && queue.conflicts.is_empty() {
+        return rsx! {};
+    }
+
+    let conflict_elements = queue.conflicts.iter().map(|conflict| {
+        rsx! {
+            tr {
+                key: "{conflict.sale.request.tx_id}",
+                td { {conflict.sale.request.tx_id.chars().take(8).collect::<String>()} }
+                td { {amount_pretty(conflict.sale.total)} }
+                td { class: "text-left", {conflict.reason.clone()} }
+            }
+        }
+    });
+
+    rsx! {
+        div {
+            class: "absolute bottom-2 right-2 flex flex-col gap-2 items-end",
+            {if show_conflicts() {
+                rsx! {
+                    div {
+                        class: "card w-108 max-h-96 bg-base-100 shadow-sm p-2 overflow-y-auto",
+                        table {
+                            class: "table table-sm",
+                            tbody { {conflict_elements} }
+                        }
+                        div {
+                            class: "flex gap-2 justify-center",
+                            button { class: "btn btn-primary btn-sm", onclick: move |_| show_conflicts.set(false), "Close" }
+                            button {
+                                class: "btn btn-error btn-sm",
+                                onclick: move |_| {
+                                    clear_conflicts();
+                                    show_conflicts.set(false);
+                                },
+                                "Clear"
+                            }
+                        }
+                    }
+                }
+            } else {
+                rsx! {}
+            }}
+            div {
+                class: "flex gap-2",
+                {if OFFLINE() {
+                    rsx! {
+                        div {
+                            class: "alert alert-warning text-lg",
+                            {format!("OFFLINE: {} sale(s) queued, will send when the server returns", queue.pending.len())}
+                        }
+                    }
+                } else {
+                    rsx! {}
+                }}
+                {if !queue.conflicts.is_empty() {
+                    rsx! {
+                        button {
+                            class: "alert alert-error text-lg",
+                            onclick: move |_| show_conflicts.set(!show_conflicts()),
+                            {format!("{} offline sale(s) need review", queue.conflicts.len())}
+                        }
+                    }
+                } else {
+                    rsx! {}
+                }}
+            }
+        }
+    }
+}
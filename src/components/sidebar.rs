@@ -2,10 +2,20 @@ use dioxus::prelude::*;
 
 use crate::{
     forms::Form,
+    health::{ServerStatus, SERVER_STATUS},
+    offline::OFFLINE,
 };
 
 #[component]
 pub fn Sidebar(navigator: Signal<Form>) -> Element {
+    let (status_class, status_tip) = match SERVER_STATUS() {
+        _ if OFFLINE() => ("status-warning", "Offline, sales are being queued".to_string()),
+        ServerStatus::Unknown => ("status-neutral", "Checking server...".to_string()),
+        ServerStatus::Ready { version, uptime_secs } => ("status-success", format!("Connected to Radix {}, up {}h {}m", version, uptime_secs / 3600, uptime_secs % 3600 / 60)),
+        ServerStatus::Degraded { problems } => ("status-error", format!("Server has a problem: {}", problems.join(", "))),
+        ServerStatus::Unreachable => ("status-error", "Server unreachable".to_string()),
+    };
+
     rsx! {
         div {
             class: "flex flex-col gap-1 menu bg-base-200 rounded-box p-1 h-full rounded-none",
@@ -21,6 +31,11 @@ pub fn Sidebar(navigator: Signal<Form>) -> Element {
                 onclick: move |_| { navigator.set(Form::Balance); },
                 dangerous_inner_html: include_str!("../../assets/balance.svg")
             }
+            div {
+                class: "flex justify-center items-center mt-auto w-14 h-14 tooltip tooltip-right",
+                "data-tip": status_tip,
+                div { class: "status status-lg {status_class}" }
+            }
         }
     }
 }
@@ -0,0 +1,90 @@
+use std::{path::PathBuf, sync::{LazyLock, RwLock}};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+pub static CONFIG: LazyLock<Config> = LazyLock::new(|| Config::load(Args::parse()));
+
+// the cashier can point the register at a discovered server while it's running
+static SERVER_URL: LazyLock<RwLock<String>> = LazyLock::new(|| RwLock::new(CONFIG.server_url.clone()));
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Path to the TOML config file
+    #[arg(short, long, default_value = "aster.toml")]
+    config: PathBuf,
+    /// Base URL of the Radix Asteris server, overrides the config file
+    #[arg(short, long)]
+    server_url: Option<String>,
+}
+
+/// Read from `aster.toml` (or `--config`), any key left out falls back to its default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(skip)]
+    pub path: PathBuf,
+    pub server_url: String,
+    pub request_timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    /// How much, in cents, an account may be charged in total while the register can't reach the server
+    pub offline_credit_limit: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("aster.toml"),
+            server_url: "http://localhost:5555".to_string(),
+            request_timeout_secs: 5,
+            connect_timeout_secs: 1,
+            offline_credit_limit: 1000,
+        }
+    }
+}
+
+impl Config {
+    fn load(args: Args) -> Self {
+        let mut config = match std::fs::read_to_string(&args.config) {
+            Ok(contents) => toml::from_str(&contents)
+                .unwrap_or_else(|e| panic!("Malformed config file {}: {}", args.config.display(), e)),
+            Err(e) => {
+                tracing::warn!("Unable to read config file {}, using defaults: {}", args.config.display(), e);
+                Config::default()
+            }
+        };
+
+        config.path = args.config;
+        if let Some(server_url) = args.server_url {
+            config.server_url = server_url;
+        }
+
+        config
+    }
+}
+
+/// Every request to the server goes through here so there's exactly one place that knows where it lives.
+pub fn api_url(path: &str) -> String {
+    format!("{}{}", server_url().trim_end_matches('/'), path)
+}
+
+pub fn server_url() -> String {
+    SERVER_URL.read().map(|url| url.clone()).unwrap_or_else(|_| CONFIG.server_url.clone())
+}
+
+/// Switches to another server and remembers it in the config file for the next start.
+pub fn set_server_url(url: String) {
+    tracing::info!("Switching server to {}", url);
+    if let Ok(mut server_url) = SERVER_URL.write() {
+        *server_url = url.clone();
+    }
+
+    let config = Config { server_url: url, ..CONFIG.clone() };
+    let result = toml::to_string(&config)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| std::fs::write(&config.path, contents).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        tracing::error!("Unable to save server to {}: {}", config.path.display(), e);
+    }
+}
@@ -0,0 +1,139 @@
+use std::{collections::HashMap, time::Duration};
+
+use dioxus::prelude::*;
+use model::{Account, Item, SyncEvent};
+
+use crate::{
+    offline::OFFLINE,
+    util::{try_sync_accounts, try_sync_pricebook},
+};
+
+// the server sends a keep-alive every 15 seconds, so anything quieter than this is a dead connection
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(40);
+
+/// Keeps the pricebook and accounts patched from the server's event stream, reconnecting whenever it drops.
+pub async fn subscribe_events(pricebook: Signal<HashMap<u32, Item>>, accounts: Signal<HashMap<u32, Account>>) {
+    loop {
+        if let Err(e) = listen(pricebook, accounts).await {
+            tracing::warn!("Event stream dropped: {}", e);
+        }
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+}
+
+async fn listen(pricebook: Signal<HashMap<u32, Item>>, accounts: Signal<HashMap<u32, Account>>) -> Result<(), String> {
+    let mut res = crate::EVENTS_CLIENT
+        .get(crate::config::api_url("/events"))
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| e.to_string())?;
+
+    tracing::info!("Subscribed to server events");
+
+    // anything that changed while we weren't listening never made it into the stream
+    if !OFFLINE() {
+        try_sync_pricebook(pricebook).await;
+        try_sync_accounts(accounts).await;
+    }
+
+    // raw bytes until a whole frame is in, a character can be split across two chunks
+    let mut buffer: Vec<u8> = Vec::new();
+    loop {
+        let chunk = tokio::time::timeout(STREAM_IDLE_TIMEOUT, res.chunk())
+            .await
+            .map_err(|_| "no keep-alive from server".to_string())?
+            .map_err(|e| e.to_string())?
+            .ok_or("server closed the stream".to_string())?;
+        buffer.extend_from_slice(&chunk);
+
+        for data in take_frames(&mut buffer) {
+            if data.is_empty() {
+                continue; // keep-alive
+            }
+
+            match serde_json::from_str::<SyncEvent>(&data) {
+                Ok(event) => apply_event(event, pricebook, accounts),
+                Err(e) => tracing::error!("Got malformed event from Radix: {:?}", e),
+            }
+        }
+    }
+}
+
+/// Takes every whole frame off the front of the buffer and returns the data in each, leaving a partial frame
+/// where it is until the rest of it comes in.
+fn take_frames(buffer: &mut Vec<u8>) -> Vec<String> {
+    let mut frames = Vec::new();
+    while let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+        let frame = buffer.drain(..end + 2).collect::<Vec<u8>>();
+        let data = String::from_utf8_lossy(&frame[..end])
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(str::trim_start)
+            .collect::<String>();
+        frames.push(data);
+    }
+    frames
+}
+
+fn apply_event(event: SyncEvent, mut pricebook: Signal<HashMap<u32, Item>>, mut accounts: Signal<HashMap<u32, Account>>) {
+    // offline sales have already been taken out of the cached balances, the reconnect resync sorts them out
+    if OFFLINE() {
+        return;
+    }
+
+    tracing::debug!("Applying server event: {:?}", event);
+    match event {
+        SyncEvent::AccountChanged { account } => {
+            accounts.write().insert(account.id, account);
+        },
+        SyncEvent::AccountRemoved { account_id } => {
+            accounts.write().remove(&account_id);
+        },
+        SyncEvent::ItemChanged { item, pricebook_version } => {
+            pricebook.write().insert(item.id, item);
+            *crate::PRICEBOOK_VERSION.write() = pricebook_version;
+        },
+        SyncEvent::ItemRemoved { item_id, pricebook_version } => {
+            pricebook.write().remove(&item_id);
+            *crate::PRICEBOOK_VERSION.write() = pricebook_version;
+        },
+        SyncEvent::ItemsChanged { items, pricebook_version } => {
+            pricebook.write().extend(items.into_iter().map(|item| (item.id, item)));
+            *crate::PRICEBOOK_VERSION.write() = pricebook_version;
+        },
+        SyncEvent::BunkChanged { bunk } => {
+            crate::BUNKS.write().insert(bunk.id, bunk);
+        },
+        SyncEvent::BunkRemoved { bunk_id } => {
+            crate::BUNKS.write().remove(&bunk_id);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::take_frames;
+
+    #[test]
+    fn frame_split_across_reads() {
+        let frame = "data: {\"name\":\"Crème brûlée\"}\n\n".as_bytes();
+        // split inside the multi-byte "è"
+        let split = frame.iter().position(|&b| b == 0xC3).unwrap() + 1;
+
+        let mut buffer = frame[..split].to_vec();
+        assert!(take_frames(&mut buffer).is_empty());
+        assert_eq!(buffer.len(), split);
+
+        buffer.extend_from_slice(&frame[split..]);
+        assert_eq!(take_frames(&mut buffer), vec!["{\"name\":\"Crème brûlée\"}".to_string()]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn several_frames_in_one_read() {
+        let mut buffer = b"data: {\"a\":1}\n\n:keep-alive\n\nevent: message\ndata: {\"b\":2}\n\ndata: {\"c\"".to_vec();
+        assert_eq!(take_frames(&mut buffer), vec!["{\"a\":1}".to_string(), String::new(), "{\"b\":2}".to_string()]);
+        assert_eq!(buffer, b"data: {\"c\"");
+    }
+}
@@ -2,21 +2,23 @@ mod account_ops;
 
 use std::collections::HashMap;
 
-use account_ops::{add_balance, remove_balance};
+use account_ops::{add_balance, add_bunk_balance, remove_balance};
 use dioxus::prelude::*;
 use model::Account;
 
-use crate::{components::searchbox::SearchBox, util::{amount_pretty, parse_cash_value, try_sync_accounts}};
+use crate::{components::{bunkfilter::BunkFilter, searchbox::SearchBox}, util::{amount_pretty, parse_cash_value, try_sync_accounts}};
 
 #[component]
 pub fn Balance(accounts: Signal<HashMap<u32, Account>>) -> Element {
     let mut selected_account = use_signal(|| None);
     let mut display_amount: Signal<String> = use_signal(|| "".to_string());
+    let bunk_filter: Signal<Option<u32>> = use_signal(|| None);
 
     let mut status: Signal<Option<String>> = use_signal(|| None);
 
     let accounts_elements = accounts()
         .into_iter()
+        .filter(|(_, account)| bunk_filter().is_none_or(|bunk| account.bunk == bunk))
         .map(|(id, account)| {
             let id = id.clone();
             rsx! {
@@ -53,7 +55,11 @@ pub fn Balance(accounts: Signal<HashMap<u32, Account>>) -> Element {
     rsx! {
         div {
             class: format!("flex flex-col grow m-2 gap-2 {}", if status().is_some() { "blur-sm" } else { "" }),
-            SearchBox { on_input: move |_| {} }
+            div {
+                class: "flex gap-2 items-center",
+                SearchBox { on_input: move |_| {} }
+                BunkFilter { selected: bunk_filter, accounts }
+            }
             div {
                 class: "flex-1 bg-base-200 rounded-box w-full overflow-y-auto",
                 table {
@@ -88,7 +94,7 @@ pub fn Balance(accounts: Signal<HashMap<u32, Account>>) -> Element {
                         oninput: move |e| {
                             display_amount.set(e.value());
                         },
-                        disabled: selected_account().is_none(),
+                        disabled: selected_account().is_none() && bunk_filter().is_none(),
                         value: display_amount()
                     }
                     button {
@@ -131,6 +137,29 @@ pub fn Balance(accounts: Signal<HashMap<u32, Account>>) -> Element {
                         },
                         "Remove"
                     }
+                    {if let Some(bunk) = bunk_filter() {
+                        rsx! {
+                            button {
+                                class: "my-auto w-48 btn btn-info py-8 my-2! text-base-200 text-2xl",
+                                onclick: move |_| async move {
+                                    status.set(Some(match parse_cash_value(display_amount()) {
+                                        Ok(amount) => match add_bunk_balance(bunk, amount).await {
+                                            Some(summary) => {
+                                                try_sync_accounts(accounts).await;
+                                                display_amount.set("".to_string());
+                                                summary
+                                            },
+                                            None => "Failed to update balances, please notify a manager.".to_string(),
+                                        },
+                                        Err(()) => "Invalid amount provided.".to_string(),
+                                    }));
+                                },
+                                {format!("Add to Bunk {}", bunk)}
+                            }
+                        }
+                    } else {
+                        rsx! {}
+                    }}
                 }
             }
         }
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use dioxus::signals::{Signal, Writable};
-use model::{Account, BalanceUpdate, UpdateOperation};
+use model::{Account, BalanceUpdate, BunkBalanceUpdate, UpdateOperation};
 
 pub async fn add_balance(account: u32, amount: u32) -> bool {
     let balance_update = BalanceUpdate {
@@ -23,9 +23,35 @@ pub async fn remove_balance(account: u32, amount: u32) -> bool {
     update_balance(balance_update).await
 }
 
+/// Adds the same amount to every open account in a bunk, returning the server's summary of what it did.
+pub async fn add_bunk_balance(bunk: u32, amount: u32) -> Option<String> {
+    let bunk_update = BunkBalanceUpdate {
+        bunk,
+        amount,
+        operation: UpdateOperation::Add
+    };
+
+    match crate::CLIENT
+        .post(crate::config::api_url("/accounts/balance/bunk"))
+        .json(&bunk_update)
+        .send()
+        .await {
+            Ok(res) if res.status() == 200 => {
+                match res.json::<String>().await {
+                    Ok(summary) => return Some(summary),
+                    Err(e) => tracing::error!("Error parsing bunk balance update response: {:?}", e),
+                }
+            },
+            Ok(res) => tracing::error!("Error code {} returned for bunk balance update request: {:?}", res.status(), res.text().await),
+            Err(e) => tracing::error!("Error sending bunk balance update request: {:?}", e),
+    }
+
+    None
+}
+
 pub async fn update_balance(balance_update: BalanceUpdate) -> bool {
     match crate::CLIENT
-        .post("http://localhost:5555/accounts/balance")
+        .post(crate::config::api_url("/accounts/balance"))
         .json(&balance_update)
         .send()
         .await {
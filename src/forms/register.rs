@@ -1,4 +1,4 @@
-mod payment;
+pub mod payment;
 
 use std::collections::HashMap;
 
@@ -18,6 +18,8 @@ struct TransactionState {
     tx_id: String,
     items: HashMap<u32, u32>,
     remaining_amount: Option<u32>,
+    /// Picked in the charge dialog, kept for the rest of the sale so the cart can flag what this camper can't buy
+    account_id: Option<u32>,
 }
 
 impl TransactionState {
@@ -26,10 +28,23 @@ impl TransactionState {
             tx_id: Uuid::new_v4().to_string(),
             items: HashMap::new(),
             remaining_amount: None,
+            account_id: None,
         }
     }
 }
 
+/// Items in the cart the account isn't allowed to buy, by id.
+fn blocked_in_cart(account: &Account, pricebook: &HashMap<u32, Item>) -> Vec<u32> {
+    let mut blocked = TRANSACTION_STATE()
+        .items
+        .keys()
+        .filter(|id| pricebook.get(id).is_some_and(|item| account.blocks(item)))
+        .copied()
+        .collect::<Vec<_>>();
+    blocked.sort();
+    blocked
+}
+
 #[derive(PartialEq)]
 pub enum PurchaseStage {
     None,
@@ -40,8 +55,12 @@ pub enum PurchaseStage {
 #[component]
 pub fn Transaction(
     pricebook: Signal<HashMap<u32, Item>>,
+    accounts: Signal<HashMap<u32, Account>>,
     purchase_stage: Signal<PurchaseStage>,
 ) -> Element {
+    let account = TRANSACTION_STATE().account_id.and_then(|id| accounts().get(&id).cloned());
+    let blocked = account.as_ref().map(|account| blocked_in_cart(account, &pricebook())).unwrap_or_default();
+
     let remove_item = move |plu: u32| {
         let mut new_tx = TRANSACTION_STATE().clone();
         new_tx.items.remove(&plu);
@@ -61,8 +80,14 @@ pub fn Transaction(
     );
 
     let tx_item_row_as_element = |item: &Item, id: u32, qty: u32| {
+        let restricted = blocked.contains(&id);
         rsx! {
             tr {
+                class: if restricted { "text-error" } else { "" },
+                title: match (restricted, &account) {
+                    (true, Some(account)) => format!("Blocked for {}", account.name),
+                    _ => String::new(),
+                },
                 td { {qty.to_string()} }
                 td { {item.name.clone()} }
                 td { {amount_pretty(item.price * qty)} }
@@ -91,6 +116,20 @@ pub fn Transaction(
             div {
                 class: "flex grow flex-col bg-base-200 rounded-box overflow-auto",
                 div { class: "text-3xl text-center my-3", "Transaction" }
+                {account.as_ref().map(|account| rsx! {
+                    div {
+                        class: "flex justify-center items-center gap-2 text-lg",
+                        {account.name.clone()}
+                        button {
+                            class: "btn btn-ghost btn-xs",
+                            onclick: move |_| TRANSACTION_STATE.signal().write().account_id = None,
+                            "Clear"
+                        }
+                    }
+                    {(!blocked.is_empty()).then(|| rsx! {
+                        div { class: "text-center text-error", {format!("{} item(s) in the cart are blocked for this account", blocked.len())} }
+                    })}
+                })}
                 Divider {}
                 table {
                     class: "table",
@@ -129,6 +168,13 @@ pub fn Inventory(
     pricebook: Signal<HashMap<u32, Item>>,
 ) -> Element {
     let mut search_candidate = use_signal(|| "".to_string());
+    // None shows everything, Some(None) the items without a category
+    let mut category_tab: Signal<Option<Option<String>>> = use_signal(|| None);
+
+    let mut categories = pricebook().values().filter_map(|item| item.category.clone()).collect::<Vec<_>>();
+    categories.sort_by_key(|category| category.to_lowercase());
+    categories.dedup();
+    let has_uncategorized = !categories.is_empty() && pricebook().values().any(|item| item.category.is_none());
 
     let add_one_item = move |plu: u32| {
         let mut new_tx = TRANSACTION_STATE();
@@ -140,6 +186,7 @@ pub fn Inventory(
         let pricebook = pricebook();
         let items = pricebook
             .iter()
+            .filter(|(_, v)| category_tab().is_none_or(|tab| v.category == tab))
             .filter(|(k, v)| {
                 v.name
                     .to_lowercase()
@@ -152,12 +199,21 @@ pub fn Inventory(
             .map(|(k, v)| {
                 rsx! {
                     tr {
-                        class: "hover:bg-base-300",
+                        class: format!("hover:bg-base-300 {}", if v.out_of_stock() { "opacity-50" } else { "" }),
                         onclick: move |_| add_one_item(k.clone()),
                         td { {format!("{:04}", k)} }
                         td { {v.name.clone()} }
                         td { {amount_pretty(v.price)} }
                         td { {v.gtin.map(|g| format!("{:10}", g)).unwrap_or("—".to_string())} }
+                        td {
+                            {if v.out_of_stock() {
+                                rsx! { span { class: "badge badge-error", "Out" } }
+                            } else if v.low_on_stock() {
+                                rsx! { span { class: "badge badge-warning", {format!("{} left", v.stock.unwrap_or_default())} } }
+                            } else {
+                                rsx! {}
+                            }}
+                        }
                     }
                 }
             });
@@ -168,6 +224,34 @@ pub fn Inventory(
         div {
             class: "flex flex-col grow gap-2",
             SearchBox { on_input: move |val| search_candidate.set(val) }
+            {(!categories.is_empty()).then(|| rsx! {
+                div {
+                    class: "flex flex-wrap gap-2",
+                    button {
+                        class: format!("btn btn-lg {}", if category_tab().is_none() { "btn-primary" } else { "" }),
+                        onclick: move |_| category_tab.set(None),
+                        "All"
+                    }
+                    for category in categories.clone() {
+                        button {
+                            key: "{category}",
+                            class: format!("btn btn-lg {}", if category_tab() == Some(Some(category.clone())) { "btn-primary" } else { "" }),
+                            onclick: {
+                                let category = category.clone();
+                                move |_| category_tab.set(Some(Some(category.clone())))
+                            },
+                            {category.clone()}
+                        }
+                    }
+                    {has_uncategorized.then(|| rsx! {
+                        button {
+                            class: format!("btn btn-lg {}", if category_tab() == Some(None) { "btn-primary" } else { "" }),
+                            onclick: move |_| category_tab.set(Some(None)),
+                            "Other"
+                        }
+                    })}
+                }
+            })}
             div {
                 class: "flex-1 bg-base-200 rounded-box w-full overflow-y-auto",
                 table {
@@ -204,11 +288,11 @@ pub fn Register(
     rsx! {
         div {
             class: format!("flex grow m-2 gap-2 {}", if !purchase_stage.read().eq(&PurchaseStage::None) { "blur-sm" } else { "" }),
-            Transaction { pricebook, purchase_stage }
+            Transaction { pricebook, accounts, purchase_stage }
             Inventory { pricebook }
         }
 
-        Payment { total: tx_total, accounts: accounts, purchase_stage }
+        Payment { total: tx_total, pricebook, accounts: accounts, purchase_stage }
     }
 }
 
@@ -1,15 +1,32 @@
 use std::collections::HashMap;
 
 use dioxus::prelude::*;
-use model::{Account, TransactionMethod, TransactionRequest, TransactionStatus, TxEntry};
+use model::{Account, Item, SpendingLimit, TransactionMethod, TransactionRejection, TransactionRequest, TransactionStatus, TxEntry};
 
-use crate::{forms::register::{PurchaseStage, TransactionState, TRANSACTION_STATE}, util::{amount_pretty, parse_cash_value, try_sync_accounts}};
+use crate::{components::bunkfilter::BunkFilter, forms::register::{blocked_in_cart, PurchaseStage, TransactionState, TRANSACTION_STATE}, offline::{queue_credit_sale, queue_sale, OFFLINE}, util::{amount_pretty, parse_cash_value, try_sync_accounts, try_sync_pricebook}};
+
+pub enum DispatchError {
+    Rejected(TransactionRejection),
+    Failed,
+    Unreachable,
+}
+
+fn build_request(method: TransactionMethod) -> TransactionRequest {
+    TransactionRequest {
+        tx_id: TRANSACTION_STATE().tx_id,
+        items: TRANSACTION_STATE().items.iter().map(|(&k, &v)| TxEntry { id: k, quantity: v }).collect(),
+        method,
+        pricebook_version: Some(crate::PRICEBOOK_VERSION()),
+        remaining: TRANSACTION_STATE().remaining_amount,
+        offline_total: None,
+    }
+}
 
 pub async fn dispatch_transaction(
     transaction_request: TransactionRequest,
-) -> Option<TransactionStatus> {
+) -> Result<TransactionStatus, DispatchError> {
     match crate::CLIENT
-        .post("http://localhost:5555/transaction")
+        .post(crate::config::api_url("/transaction"))
         .json(&transaction_request)
         .send()
         .await
@@ -17,17 +34,53 @@ pub async fn dispatch_transaction(
         Ok(res) => {
             if res.status() == 200 {
                 match res.json::<TransactionStatus>().await {
-                    Ok(res) => return Some(res),
+                    Ok(res) => return Ok(res),
                     Err(e) => tracing::error!("Error parsing tx response: {:?}", e),
                 }
+            } else if res.status() == 422 {
+                match res.json::<TransactionRejection>().await {
+                    Ok(rejection) => return Err(DispatchError::Rejected(rejection)),
+                    Err(e) => tracing::error!("Error parsing tx rejection: {:?}", e),
+                }
             } else if res.status() == 500 {
                 tracing::error!("Error code 500 returned for tx request: {:?}", res.text().await);
             }
         },
-        Err(e) => tracing::error!("Error sending tx request: {:?}", e),
+        Err(e) => {
+            tracing::error!("Error sending tx request: {:?}", e);
+            return Err(DispatchError::Unreachable);
+        },
     };
 
-    None
+    Err(DispatchError::Failed)
+}
+
+/// The server knows something about the pricebook that we don't, so pull it down again and
+/// drop whatever it refused from the cart before the cashier retries.
+pub async fn handle_rejection(rejection: TransactionRejection, pricebook: Signal<HashMap<u32, Item>>) -> String {
+    tracing::warn!("Transaction rejected by server: {:?}", rejection);
+    try_sync_pricebook(pricebook).await;
+
+    if let TransactionRejection::InvalidItems { unknown_items, zero_quantity } = &rejection {
+        TRANSACTION_STATE.signal().write().items.retain(|id, _| !unknown_items.contains(id) && !zero_quantity.contains(id));
+    }
+
+    format!("{} The pricebook has been refreshed, please review the transaction.", rejection)
+}
+
+/// Prices moved under us since the last sync, so refresh before the cashier quotes the new total.
+pub async fn handle_price_change(total: u32, pricebook: Signal<HashMap<u32, Item>>) -> String {
+    tracing::warn!("Pricebook changed on the server, new total: {}", total);
+    try_sync_pricebook(pricebook).await;
+
+    format!("Prices have changed since this register last synced. The new total is {}, please confirm with the customer and try again.", amount_pretty(total))
+}
+
+pub fn go_offline() -> String {
+    tracing::warn!("Backend unreachable, switching to offline mode");
+    *OFFLINE.write() = true;
+
+    "The server can't be reached, the register is now in offline mode. Please try again.".to_string()
 }
 
 #[component]
@@ -46,26 +99,77 @@ pub fn PaymentTitle(title: &'static str, purchase_stage: Signal<PurchaseStage>)
     }
 }
 
+pub fn limit_message(limit: SpendingLimit, cap: u32, remaining: u32, remaining_today: Option<u32>) -> String {
+    let message = match limit {
+        SpendingLimit::Daily => format!("Daily limit of {} reached, {} left today.", amount_pretty(cap), amount_pretty(remaining)),
+        SpendingLimit::Weekly => format!("Weekly limit of {} reached, {} left this week.", amount_pretty(cap), amount_pretty(remaining)),
+        SpendingLimit::Transaction => format!("Over the per-sale limit of {}.", amount_pretty(cap)),
+    };
+    match remaining_today {
+        Some(today) if limit != SpendingLimit::Daily => format!("{} {} left today.", message, amount_pretty(today)),
+        _ => message,
+    }
+}
+
+pub fn blocked_message(name: &str, items: &[u32], pricebook: &HashMap<u32, Item>) -> String {
+    let items = items.iter().map(|id| pricebook.get(id).map(|item| item.name.clone()).unwrap_or(format!("item {}", id))).collect::<Vec<_>>();
+    format!("{} can't buy {}.", name, items.join(", "))
+}
+
+pub fn stock_message(items: &[u32], pricebook: &HashMap<u32, Item>) -> String {
+    let items = items.iter().map(|id| match pricebook.get(id) {
+        Some(item) => format!("{} ({} left)", item.name, item.stock.unwrap_or_default().max(0)),
+        None => format!("item {}", id),
+    }).collect::<Vec<_>>();
+    format!("Not enough on hand of {}. Please take them off the sale or lower the quantity.", items.join(", "))
+}
+
+fn limits_summary(account: &Account) -> Option<String> {
+    let limits = [(account.daily_limit, "day"), (account.weekly_limit, "week"), (account.transaction_limit, "sale")]
+        .into_iter()
+        .filter_map(|(limit, per)| limit.map(|limit| format!("{}/{}", amount_pretty(limit), per)))
+        .collect::<Vec<_>>();
+    (!limits.is_empty()).then(|| format!("Limits: {}", limits.join(", ")))
+}
+
 #[component]
-pub fn PaymentCharge(accounts: Signal<HashMap<u32, Account>>, purchase_stage: Signal<PurchaseStage>) -> Element {
+pub fn PaymentCharge(total: u32, pricebook: Signal<HashMap<u32, Item>>, accounts: Signal<HashMap<u32, Account>>, purchase_stage: Signal<PurchaseStage>) -> Element {
     let mut account_query: Signal<String> = use_signal(|| "".to_string());    
-    let mut account_id: Signal<Option<u32>> = use_signal(|| None);
+    let bunk_filter: Signal<Option<u32>> = use_signal(|| None);
     let mut info: Signal<String> = use_signal(|| "".to_string());
 
+    let account_id = move || TRANSACTION_STATE().account_id;
+    let select_account = move |id: Option<u32>| TRANSACTION_STATE.signal().write().account_id = id;
+
     let finalize = move || async move {
         if account_id().is_none() {
             info.set("Invalid account selected. Please choose a valid account.".to_string());
             return; // No account selected
         }
 
-        match dispatch_transaction(
-            TransactionRequest {
-                tx_id: TRANSACTION_STATE().tx_id,
-                items: TRANSACTION_STATE().items.iter().map(|(&k, &v)| TxEntry { id: k, quantity: v }).collect(),
-                method: TransactionMethod::Credit { account_id: account_id().unwrap() },
+        let request = build_request(TransactionMethod::Credit { account_id: account_id().unwrap() });
+
+        if OFFLINE() {
+            let Some(account) = accounts().get(&account_id().unwrap()).cloned() else {
+                info.set("Invalid account selected. Please choose a valid account.".to_string());
+                return;
+            };
+            match queue_credit_sale(request, &account, total, &pricebook()) {
+                Ok(()) => {
+                    // keep the cached balance honest until the server confirms the sale
+                    if let Some(account) = accounts.write().get_mut(&account.id) {
+                        account.credit -= account.discounted(total) as i32;
+                    }
+                    TRANSACTION_STATE.signal().set(TransactionState::new());
+                    info.set("Sale queued offline, it will be charged when the server returns.".to_string());
+                },
+                Err(reason) => info.set(reason),
             }
-        ).await {
-            Some(tx_res) => {
+            return;
+        }
+
+        match dispatch_transaction(request).await {
+            Ok(tx_res) => {
                 match tx_res {
                     TransactionStatus::Success { .. } => {
                         try_sync_accounts(accounts).await;
@@ -77,11 +181,37 @@ pub fn PaymentCharge(accounts: Signal<HashMap<u32, Account>>, purchase_stage: Si
                     TransactionStatus::InvalidAccount { .. } => {
                         info.set("Invalid account selected. Please choose a valid account.".to_string());
                     }
+                    TransactionStatus::LimitExceeded { limit, cap, remaining, remaining_today } => {
+                        info.set(limit_message(limit, cap, remaining, remaining_today));
+                    }
+                    TransactionStatus::AccountClosed { .. } => {
+                        try_sync_accounts(accounts).await;
+                        info.set("This account has been closed out, nothing more can be charged to it.".to_string());
+                    }
+                    TransactionStatus::OutOfStock { items } => {
+                        try_sync_pricebook(pricebook).await;
+                        info.set(stock_message(&items, &pricebook()));
+                    }
+                    TransactionStatus::ItemsBlocked { items } => {
+                        // restrictions may have been set since the last sync, so the cart can flag them too
+                        try_sync_accounts(accounts).await;
+                        let name = accounts().get(&account_id().unwrap_or_default()).map(|account| account.name.clone()).unwrap_or("This account".to_string());
+                        info.set(blocked_message(&name, &items, &pricebook()));
+                    }
+                    TransactionStatus::PricesChanged { total, .. } => {
+                        info.set(handle_price_change(total, pricebook).await);
+                    }
                     _ => {},
                 }
             },
-            None => {
+            Err(DispatchError::Rejected(rejection)) => {
+                info.set(handle_rejection(rejection, pricebook).await);
+            },
+            Err(DispatchError::Failed) => {
                 info.set("An error occurred. Please try again or notify a manager.".to_string());
+            },
+            Err(DispatchError::Unreachable) => {
+                info.set(go_offline());
             }
         }
     };
@@ -98,25 +228,37 @@ pub fn PaymentCharge(accounts: Signal<HashMap<u32, Account>>, purchase_stage: Si
                     placeholder: "Search accounts...",
                     value: account_query(),
                 }
+                BunkFilter { selected: bunk_filter, accounts }
+                {account_id().and_then(|id| accounts().get(&id).and_then(limits_summary)).map(|summary| rsx! { div { class: "text-sm", {summary} } })}
+                {account_id()
+                    .and_then(|id| accounts().get(&id).cloned())
+                    .map(|account| (blocked_in_cart(&account, &pricebook()), account.name))
+                    .filter(|(blocked, _)| !blocked.is_empty())
+                    .map(|(blocked, name)| rsx! { div { class: "text-sm text-error", {blocked_message(&name, &blocked, &pricebook())} } })}
                 div {                    
                     class: "overflow-y-auto max-h-28",
                     table {
                         class: "w-full text-lg",
                         {accounts()
                             .into_iter()
+                            .filter(|(_, account)| bunk_filter().is_none_or(|bunk| account.bunk == bunk))
                             .filter(|(_, account)| account.name.to_lowercase().contains(&account_query().to_lowercase()) || account.id.to_string().contains(&account_query().to_lowercase()))
                             .map(|(id, account)| {
+                                let (locked, name) = (account.locked, account.name.clone());
                                 rsx! {
                                     tr {
                                         key: "{id}",
                                         onclick: move |_| {
-                                            if account_id() == Some(id) {
-                                                account_id.set(None);
+                                            // closed out accounts stay listed so nobody goes looking for them, but can't be charged
+                                            if locked {
+                                                info.set(format!("{} has been closed out, nothing more can be charged to it.", name));
+                                            } else if account_id() == Some(id) {
+                                                select_account(None);
                                             } else {
-                                                account_id.set(Some(id));
+                                                select_account(Some(id));
                                             }
                                         },
-                                        class: format!("p-1 hover:bg-base-300 {}", if account_id() == Some(id) { "bg-base-300" } else { "" }),
+                                        class: format!("p-1 hover:bg-base-300 {} {}", if account_id() == Some(id) { "bg-base-300" } else { "" }, if locked { "opacity-50" } else { "" }),
                                         td { "{account.id}" }
                                         td { "{account.name}" }
                                         td { {amount_pretty(account.credit)} }
@@ -153,19 +295,33 @@ pub enum CashStage {
 }
 
 #[component]
-pub fn PaymentCash(purchase_stage: Signal<PurchaseStage>) -> Element {
+pub fn PaymentCash(total: u32, pricebook: Signal<HashMap<u32, Item>>, accounts: Signal<HashMap<u32, Account>>, purchase_stage: Signal<PurchaseStage>) -> Element {
     let mut cash_stage = use_signal(|| CashStage::Selection { info: None });
+    // cash sales aren't checked against restrictions, but if an account was picked for this sale the cashier should know
+    let blocked_warning = TRANSACTION_STATE()
+        .account_id
+        .and_then(|id| accounts().get(&id).cloned())
+        .map(|account| (blocked_in_cart(&account, &pricebook()), account.name))
+        .filter(|(blocked, _)| !blocked.is_empty())
+        .map(|(blocked, name)| blocked_message(&name, &blocked, &pricebook()));
     let mut custom_amount: Signal<Option<u32>> = use_signal(|| None);
 
     let finalize = move |amount: u32| async move {
-        match dispatch_transaction(
-            TransactionRequest {
-                tx_id: TRANSACTION_STATE().tx_id,
-                items: TRANSACTION_STATE().items.iter().map(|(&k, &v)| TxEntry { id: k, quantity: v }).collect(),
-                method: TransactionMethod::Cash { tender: amount },
+        let request = build_request(TransactionMethod::Cash { tender: amount });
+
+        if OFFLINE() {
+            if amount < total {
+                cash_stage.set(CashStage::Selection { info: Some("Partial cash payments can't be taken offline, please tender the full amount.".to_string()) });
+            } else {
+                queue_sale(request, total);
+                TRANSACTION_STATE.signal().set(TransactionState::new());
+                cash_stage.set(CashStage::CashBack { amount: amount - total });
             }
-        ).await {
-            Some(tx_res) => {
+            return;
+        }
+
+        match dispatch_transaction(request).await {
+            Ok(tx_res) => {
                 match tx_res {
                     TransactionStatus::Success { cash_back } => {
                         TRANSACTION_STATE.signal().set(TransactionState::new());
@@ -181,10 +337,26 @@ pub fn PaymentCash(purchase_stage: Signal<PurchaseStage>) -> Element {
                     TransactionStatus::InvalidAccount { .. } => {
                         cash_stage.set(CashStage::Selection { info: Some("How tf did you pass an account in a cash transaction bruh 😭😭".to_string()) })
                     }
+                    TransactionStatus::AccountClosed { .. } | TransactionStatus::LimitExceeded { .. } | TransactionStatus::ItemsBlocked { .. } => {
+                        cash_stage.set(CashStage::Selection { info: Some("Cash sales don't use an account, please try again.".to_string()) })
+                    }
+                    TransactionStatus::PricesChanged { total, .. } => {
+                        cash_stage.set(CashStage::Selection { info: Some(handle_price_change(total, pricebook).await) })
+                    }
+                    TransactionStatus::OutOfStock { items } => {
+                        try_sync_pricebook(pricebook).await;
+                        cash_stage.set(CashStage::Selection { info: Some(stock_message(&items, &pricebook())) })
+                    }
                 }
             },
-            None => {
+            Err(DispatchError::Rejected(rejection)) => {
+                cash_stage.set(CashStage::Selection { info: Some(handle_rejection(rejection, pricebook).await) })
+            },
+            Err(DispatchError::Failed) => {
                 cash_stage.set(CashStage::Selection { info: Some("An error occurred. Please try again or notify a manager.".to_string()) })
+            },
+            Err(DispatchError::Unreachable) => {
+                cash_stage.set(CashStage::Selection { info: Some(go_offline()) })
             }
         }
     };
@@ -196,6 +368,7 @@ pub fn PaymentCash(purchase_stage: Signal<PurchaseStage>) -> Element {
                     div {
                         class: "flex flex-col w-full gap-2",
                         {if let Some(info) = info { rsx! { div { class: "text-sm text-red-300", {info} } } } else { rsx! {} }}
+                        {blocked_warning.clone().map(|warning| rsx! { div { class: "text-sm text-warning", {warning} } })}
                         div {
                             class: "flex gap-2",
                             button { class: "grow btn btn-square btn-success btn-lg", onclick: move |_| cash_stage.set(CashStage::Confirmation { amount: 500 }), "$5" }
@@ -243,14 +416,14 @@ pub fn PaymentCash(purchase_stage: Signal<PurchaseStage>) -> Element {
 }
 
 #[component]
-pub fn Payment(total: u32, accounts: Signal<HashMap<u32, Account>>, purchase_stage: Signal<PurchaseStage>) -> Element {
+pub fn Payment(total: u32, pricebook: Signal<HashMap<u32, Item>>, accounts: Signal<HashMap<u32, Account>>, purchase_stage: Signal<PurchaseStage>) -> Element {
     if *purchase_stage.read() == PurchaseStage::None {
         return rsx! {};
     }
 
     let (title, inner) = match *purchase_stage.read() {
-        PurchaseStage::Charge => ("Account", rsx! { PaymentCharge { accounts, purchase_stage } }),
-        PurchaseStage::Cash => ("Cash", rsx! { PaymentCash { purchase_stage } }),
+        PurchaseStage::Charge => ("Account", rsx! { PaymentCharge { total, pricebook, accounts, purchase_stage } }),
+        PurchaseStage::Cash => ("Cash", rsx! { PaymentCash { total, pricebook, accounts, purchase_stage } }),
         _ => return rsx! {},
     };
 
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use dioxus::prelude::*;
+use model::Readiness;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerStatus {
+    Unknown,
+    Ready { version: String, uptime_secs: u64 },
+    // up, but something it needs (database, disk) isn't
+    Degraded { problems: Vec<String> },
+    Unreachable,
+}
+
+pub static SERVER_STATUS: GlobalSignal<ServerStatus> = GlobalSignal::new(|| ServerStatus::Unknown);
+
+/// Polls the server's readiness check for the sidebar indicator, separate from offline mode which only trips on a failed request.
+pub async fn poll_health() {
+    loop {
+        let status = check().await;
+        if *SERVER_STATUS.peek() != status {
+            tracing::info!("Server status changed: {:?}", status);
+            *SERVER_STATUS.write() = status;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn check() -> ServerStatus {
+    // a failing check comes back as a 503, but with the same body
+    let Ok(response) = crate::CLIENT.get(crate::config::api_url("/ready")).send().await else {
+        return ServerStatus::Unreachable;
+    };
+
+    match response.json::<Readiness>().await {
+        Ok(readiness) if readiness.ready => ServerStatus::Ready { version: readiness.version, uptime_secs: readiness.uptime_secs },
+        Ok(readiness) => ServerStatus::Degraded {
+            problems: readiness
+                .checks
+                .into_iter()
+                .filter(|check| !check.ok)
+                .map(|check| format!("{}: {}", check.name, check.detail.unwrap_or_default()))
+                .collect(),
+        },
+        Err(e) => {
+            tracing::error!("Got malformed readiness response from Radix: {:?}", e);
+            ServerStatus::Degraded { problems: vec!["unrecognized response from server".to_string()] }
+        },
+    }
+}
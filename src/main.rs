@@ -1,31 +1,57 @@
 mod app;
 mod components;
+mod config;
+mod events;
 mod forms;
+mod health;
+mod offline;
 mod util;
 
-use std::{sync::LazyLock, time::Duration};
+use std::{collections::HashMap, sync::LazyLock, time::Duration};
 
 use app::App;
 use dioxus::desktop::{Config, WindowBuilder};
+use dioxus::prelude::GlobalSignal;
+use model::Bunk;
 use reqwest::{header::HeaderValue, Client};
 
 use tracing::Level;
 
-pub static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+fn auth_headers() -> reqwest::header::HeaderMap {
     let mut headers = reqwest::header::HeaderMap::new();
     let auth_header = HeaderValue::from_str(include_str!("../.env").trim()).expect("Malformed auth header");
     headers.insert("x-auth-token", auth_header);
+    headers
+}
 
+pub static CLIENT: LazyLock<Client> = LazyLock::new(|| {
     Client::builder()
-        .default_headers(headers)
-        .timeout(Duration::from_secs(5))
-        .connect_timeout(Duration::from_secs(1))
+        .default_headers(auth_headers())
+        .timeout(Duration::from_secs(config::CONFIG.request_timeout_secs))
+        .connect_timeout(Duration::from_secs(config::CONFIG.connect_timeout_secs))
         .build()
         .expect("Failed to create HTTP client")
 });
 
+// the event stream stays open indefinitely, so it can't share CLIENT's request timeout
+pub static EVENTS_CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .default_headers(auth_headers())
+        .connect_timeout(Duration::from_secs(config::CONFIG.connect_timeout_secs))
+        .build()
+        .expect("Failed to create HTTP client")
+});
+
+pub static PRICEBOOK_VERSION: GlobalSignal<u64> = GlobalSignal::new(|| 0);
+pub static PRICEBOOK_SEQ: GlobalSignal<u64> = GlobalSignal::new(|| 0);
+pub static ACCOUNTS_SEQ: GlobalSignal<u64> = GlobalSignal::new(|| 0);
+// every sync carries the whole list, so it's replaced rather than patched like accounts
+pub static BUNKS: GlobalSignal<HashMap<u32, Bunk>> = GlobalSignal::new(HashMap::new);
+
 pub fn main() {
     dioxus_logger::init(Level::DEBUG).expect("failed to init logger");
+    // parse the command line up front so bad arguments fail before the window opens
+    LazyLock::force(&config::CONFIG);
     dioxus::LaunchBuilder::new()
         .with_cfg(
             Config::default().with_menu(None).with_window(
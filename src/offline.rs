@@ -0,0 +1,195 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use dioxus::prelude::*;
+use model::{Account, Item, SyncState, TransactionMethod, TransactionRequest, TransactionStatus};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    forms::register::payment::{dispatch_transaction, DispatchError},
+    util::{amount_pretty, try_sync_accounts, try_sync_pricebook},
+};
+
+const QUEUE_FILE: &str = "offline_queue.json";
+const SYNC_CACHE_FILE: &str = "sync_cache.json";
+
+pub static OFFLINE: GlobalSignal<bool> = GlobalSignal::new(|| false);
+pub static OFFLINE_QUEUE: GlobalSignal<OfflineQueue> = GlobalSignal::new(|| read_json(QUEUE_FILE).unwrap_or_default());
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct OfflineQueue {
+    pub pending: Vec<QueuedSale>,
+    pub conflicts: Vec<SaleConflict>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct QueuedSale {
+    pub request: TransactionRequest,
+    pub total: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaleConflict {
+    pub sale: QueuedSale,
+    pub reason: String,
+}
+
+fn data_path(file: &str) -> PathBuf {
+    std::env::current_dir().unwrap_or_default().join(file)
+}
+
+fn read_json<T: DeserializeOwned>(file: &str) -> Option<T> {
+    let contents = std::fs::read_to_string(data_path(file)).ok()?;
+    serde_json::from_str(&contents)
+        .inspect_err(|e| tracing::error!("Error parsing {}: {:?}", file, e))
+        .ok()
+}
+
+// write to a temp file and rename over the original so a crash mid-write can't eat the queue
+fn write_json<T: Serialize>(file: &str, value: &T) {
+    let path = data_path(file);
+    let temp_path = path.with_extension("json.tmp");
+    let result = serde_json::to_string(value)
+        .map_err(std::io::Error::from)
+        .and_then(|contents| std::fs::write(&temp_path, contents))
+        .and_then(|_| std::fs::rename(&temp_path, &path));
+
+    if let Err(e) = result {
+        tracing::error!("Error writing {}: {:?}", file, e);
+    }
+}
+
+fn update_queue(update: impl FnOnce(&mut OfflineQueue)) {
+    let mut queue = OFFLINE_QUEUE.write();
+    update(&mut queue);
+    write_json(QUEUE_FILE, &*queue);
+}
+
+pub fn cache_sync_state(sync_state: &SyncState) {
+    write_json(SYNC_CACHE_FILE, sync_state);
+}
+
+pub fn cache_accounts(accounts: &[Account]) {
+    if let Some(mut sync_state) = load_sync_cache() {
+        sync_state.accounts = accounts.to_vec();
+        cache_sync_state(&sync_state);
+    }
+}
+
+pub fn cache_pricebook(pricebook: &[Item], pricebook_version: u64) {
+    if let Some(mut sync_state) = load_sync_cache() {
+        sync_state.pricebook = pricebook.to_vec();
+        sync_state.pricebook_version = pricebook_version;
+        cache_sync_state(&sync_state);
+    }
+}
+
+pub fn load_sync_cache() -> Option<SyncState> {
+    read_json(SYNC_CACHE_FILE)
+}
+
+pub fn queue_sale(request: TransactionRequest, total: u32) {
+    tracing::info!("Queueing offline sale {}", request.tx_id);
+    update_queue(|queue| queue.pending.push(QueuedSale { request, total }));
+}
+
+/// Queues a credit sale against the cached balance, as long as the account stays under the offline limit.
+/// `total` is the sale at list price, the server takes the account's discount off when it gets it.
+pub fn queue_credit_sale(request: TransactionRequest, account: &Account, total: u32, pricebook: &HashMap<u32, Item>) -> Result<(), String> {
+    if account.locked {
+        return Err("This account has been closed out, nothing more can be charged to it.".to_string());
+    }
+
+    let mut blocked = request.items.iter().filter(|entry| pricebook.get(&entry.id).is_some_and(|item| account.blocks(item))).map(|entry| entry.id).collect::<Vec<_>>();
+    if !blocked.is_empty() {
+        blocked.sort();
+        return Err(crate::forms::register::payment::blocked_message(&account.name, &blocked, pricebook));
+    }
+
+    let charged = account.discounted(total);
+    let limit = crate::config::CONFIG.offline_credit_limit;
+    let used = offline_credit_used(account);
+    if used.saturating_add(charged) > limit {
+        return Err(format!(
+            "Offline credit limit reached for this account, only {} more can be charged until the server returns.",
+            amount_pretty(limit.saturating_sub(used))
+        ));
+    }
+
+    // daily and weekly spending lives on the server, only the per-sale limit can be checked from here
+    if account.transaction_limit.is_some_and(|limit| charged > limit) {
+        return Err(format!("Over the per-sale limit of {}.", amount_pretty(account.transaction_limit.unwrap_or_default())));
+    }
+
+    // the cached balance already has earlier offline sales taken out
+    if account.credit < charged as i32 && !account.overdraft {
+        return Err("Insufficient Credit.".to_string());
+    }
+
+    queue_sale(request, total);
+    Ok(())
+}
+
+pub fn clear_conflicts() {
+    update_queue(|queue| queue.conflicts.clear());
+}
+
+/// Credit already promised to an account by sales that haven't reached the server yet, after its discount.
+pub fn offline_credit_used(account: &Account) -> u32 {
+    OFFLINE_QUEUE()
+        .pending
+        .iter()
+        .filter(|sale| matches!(sale.request.method, TransactionMethod::Credit { account_id } if account_id == account.id))
+        .map(|sale| account.discounted(sale.total))
+        .sum()
+}
+
+/// Replays queued sales oldest first. Returns false if the server went away again, or couldn't take a sale
+/// right now, before the queue drained. Whatever is left stays queued for the next attempt.
+pub async fn replay_queue() -> bool {
+    while let Some(sale) = OFFLINE_QUEUE().pending.first().cloned() {
+        tracing::info!("Replaying offline sale {}", sale.request.tx_id);
+
+        // charged at the prices the register had, whatever they've become since, as long as the server had them too
+        let request = TransactionRequest { offline_total: Some(sale.total), ..sale.request.clone() };
+        let conflict = match dispatch_transaction(request).await {
+            Ok(TransactionStatus::Success { .. }) => None,
+            Ok(TransactionStatus::Partial { remaining }) => Some(format!("Server still expects {} for this sale.", amount_pretty(remaining))),
+            Ok(TransactionStatus::PricesChanged { total, .. }) => Some(format!("Prices changed, server total is {} but {} was collected.", amount_pretty(total), amount_pretty(sale.total))),
+            Ok(TransactionStatus::InvalidAccount { account_id }) => Some(format!("Account {} no longer exists.", account_id)),
+            Ok(TransactionStatus::LimitExceeded { limit, cap, remaining, remaining_today }) => Some(crate::forms::register::payment::limit_message(limit, cap, remaining, remaining_today)),
+            Ok(TransactionStatus::OutOfStock { items }) => Some(format!("Items {:?} had run out by the time this sale reached the server.", items)),
+            Ok(TransactionStatus::ItemsBlocked { items }) => Some(format!("The account can't buy items {:?}, they were blocked before this sale reached the server.", items)),
+            Ok(TransactionStatus::AccountClosed { account_id }) => Some(format!("Account {} was closed out before this sale reached the server.", account_id)),
+            Ok(TransactionStatus::Failure { reason }) => Some(reason),
+            Err(DispatchError::Rejected(rejection)) => Some(rejection.to_string()),
+            Err(DispatchError::Failed) => {
+                tracing::warn!("Server couldn't take offline sale {}, keeping it queued", sale.request.tx_id);
+                return false;
+            }
+            Err(DispatchError::Unreachable) => return false,
+        };
+
+        update_queue(|queue| {
+            queue.pending.remove(0);
+            if let Some(reason) = conflict {
+                tracing::warn!("Offline sale {} conflicted: {}", sale.request.tx_id, reason);
+                queue.conflicts.push(SaleConflict { sale, reason });
+            }
+        });
+    }
+
+    true
+}
+
+/// Probes the server, drains the queue and only then resyncs and leaves offline mode,
+/// so new sales can't jump ahead of the ones rung up while disconnected.
+pub async fn try_reconnect(pricebook: Signal<HashMap<u32, Item>>, accounts: Signal<HashMap<u32, Account>>) {
+    if crate::CLIENT.get(crate::config::api_url("/sync")).send().await.is_err() || !replay_queue().await {
+        return;
+    }
+
+    try_sync_pricebook(pricebook).await;
+    try_sync_accounts(accounts).await;
+    *OFFLINE.write() = false;
+    tracing::info!("Backend reachable again, leaving offline mode");
+}
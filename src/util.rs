@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use dioxus::signals::{Signal, Writable};
-use model::Account;
+use model::{Account, Item, SyncKind, SyncState};
 
 pub fn amount_pretty(amount: u32) -> String {
     format!("${:.02}", (amount) as f32 / 100.0)
@@ -32,17 +32,53 @@ pub fn parse_cash_value(input_amount: String) -> Result<u32, ()> {
     Err(())
 }
 
-pub async fn try_sync_accounts(mut accounts: Signal<HashMap<u32, Account>>) {
-    if let Ok(res) = crate::CLIENT
-        .get("http://localhost:5555/accounts")
+// each caller applies one half, so only ask for that half
+async fn fetch_sync(since: u64, only: SyncKind) -> Option<SyncState> {
+    let res = crate::CLIENT
+        .get(crate::config::api_url("/sync"))
+        .query(&[("since", since)])
+        .query(&[("only", only)])
         .send()
-        .await {
-            if let Ok(new_accounts) = res.json::<Vec<Account>>().await {
-                accounts.set(
-                    new_accounts.into_iter()
-                        .map(|a| (a.id, a))
-                        .collect::<HashMap<u32, Account>>()
-                );
-            }
-        }
+        .await
+        .ok()?;
+
+    res.json::<SyncState>()
+        .await
+        .inspect_err(|e| tracing::error!("Got malformed sync state from Radix: {:?}", e))
+        .ok()
+}
+
+// a watermark of 0 means we never synced (or came up from the offline cache), so the server sent everything
+fn apply_sync<T>(map: &mut HashMap<u32, T>, since: u64, changed: Vec<T>, removed: Vec<u32>, id: impl Fn(&T) -> u32) {
+    if since == 0 {
+        map.clear();
+    }
+    for id in removed {
+        map.remove(&id);
+    }
+    for entry in changed {
+        map.insert(id(&entry), entry);
+    }
+}
+
+pub async fn try_sync_accounts(mut accounts: Signal<HashMap<u32, Account>>) {
+    let since = crate::ACCOUNTS_SEQ();
+    if let Some(sync_state) = fetch_sync(since, SyncKind::Accounts).await {
+        let mut accounts = accounts.write();
+        apply_sync(&mut accounts, since, sync_state.accounts, sync_state.removed_accounts, |a| a.id);
+        *crate::ACCOUNTS_SEQ.write() = sync_state.seq;
+        *crate::BUNKS.write() = sync_state.bunks.into_iter().map(|b| (b.id, b)).collect();
+        crate::offline::cache_accounts(&accounts.values().cloned().collect::<Vec<_>>());
+    }
+}
+
+pub async fn try_sync_pricebook(mut pricebook: Signal<HashMap<u32, Item>>) {
+    let since = crate::PRICEBOOK_SEQ();
+    if let Some(sync_state) = fetch_sync(since, SyncKind::Pricebook).await {
+        let mut pricebook = pricebook.write();
+        apply_sync(&mut pricebook, since, sync_state.pricebook, sync_state.removed_items, |i| i.id);
+        *crate::PRICEBOOK_SEQ.write() = sync_state.seq;
+        *crate::PRICEBOOK_VERSION.write() = sync_state.pricebook_version;
+        crate::offline::cache_pricebook(&pricebook.values().cloned().collect::<Vec<_>>(), sync_state.pricebook_version);
+    }
 }